@@ -0,0 +1,420 @@
+use crate::{tr, AtomicWriter, Config, FileReport, UiLang};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Mutex, OnceLock, PoisonError};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// 备份清单文件名，位于 `--dir` 下；每行一条 JSON 记录，转换过程中逐条追加
+pub const MANIFEST_FILE: &str = ".gbk2utf8-manifest.jsonl";
+
+/// 转换时写入的备份清单，`restore` 和 `clean-backups` 子命令据此找到备份文件
+#[derive(Debug, Default)]
+pub struct BackupManifest {
+    pub entries: Vec<BackupEntry>,
+}
+
+/// 一个已转换文件及其备份，路径位于 `--dir` 下时保存为相对路径，否则保存为绝对路径
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BackupEntry {
+    pub path: PathBuf,
+    pub backup: PathBuf,
+    pub encoding: Option<String>,
+    pub target: Option<String>,
+}
+
+/// `restore` 或 `clean-backups` 的执行结果
+#[derive(Debug, Default)]
+pub struct BackupRunResult {
+    /// 已恢复（或已删除备份）的条目
+    pub processed: Vec<BackupEntry>,
+    pub errors: HashMap<PathBuf, io::Error>,
+}
+
+/// 本次运行的备份子目录名（UTC 时间），同一进程内的所有文件共用
+static RUN_TIMESTAMP: OnceLock<String> = OnceLock::new();
+
+/// 清单文件的实际路径
+pub fn manifest_path(root_dir: &Path) -> PathBuf {
+    root_dir.join(MANIFEST_FILE)
+}
+
+impl BackupManifest {
+    /// 读取清单；文件不存在时返回空清单。运行被中断时最后一行可能只写了一半，忽略这一行
+    pub fn load(root_dir: &Path) -> io::Result<Self> {
+        let path = manifest_path(root_dir);
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e),
+        };
+
+        let mut entries = Vec::new();
+        let mut lines = content.lines().enumerate().peekable();
+        while let Some((index, line)) = lines.next() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str(line) {
+                Ok(entry) => entries.push(entry),
+                Err(_) if lines.peek().is_none() && !content.ends_with('\n') => break,
+                Err(e) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("{}:{}: {}", path.display(), index + 1, e),
+                    ))
+                }
+            }
+        }
+        Ok(Self { entries })
+    }
+
+    /// 保存清单；没有任何条目时删除清单文件
+    pub fn save(&self, root_dir: &Path) -> io::Result<()> {
+        let path = manifest_path(root_dir);
+        if self.entries.is_empty() {
+            return match fs::remove_file(&path) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+                _ => Ok(()),
+            };
+        }
+        let content: String = self.entries.iter().map(BackupEntry::to_line).collect();
+        fs::write(path, content)
+    }
+}
+
+impl BackupEntry {
+    /// 转换报告对应的清单条目，没有创建备份时返回 `None`
+    fn from_report(root_dir: &Path, report: &FileReport) -> Option<Self> {
+        let backup = report.backup.as_ref()?;
+        Some(Self {
+            path: relative_to(root_dir, &report.path),
+            backup: relative_to(root_dir, backup),
+            encoding: report.encoding.clone(),
+            target: report.target.clone(),
+        })
+    }
+
+    fn to_line(&self) -> String {
+        serde_json::to_string(self).expect("serialize backup entry") + "\n"
+    }
+}
+
+/// 转换过程中逐条追加备份清单：每创建一个备份就立即写入，
+/// 运行中途被中断时已转换的文件也能用 `restore` 恢复。可在多个工作线程间共用
+pub(crate) struct ManifestWriter {
+    root_dir: PathBuf,
+    state: Mutex<ManifestState>,
+}
+
+#[derive(Default)]
+struct ManifestState {
+    /// 第一次追加时才打开（创建）清单文件，没有备份时不产生清单
+    file: Option<fs::File>,
+    /// 第一次写入失败的错误，之后不再尝试写入
+    error: Option<io::Error>,
+}
+
+impl ManifestState {
+    fn write(&mut self, path: &Path, entry: &BackupEntry) -> io::Result<()> {
+        let file = match &mut self.file {
+            Some(file) => file,
+            None => self.file.insert(
+                fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)?,
+            ),
+        };
+        file.write_all(entry.to_line().as_bytes())
+    }
+}
+
+impl ManifestWriter {
+    pub(crate) fn new(root_dir: &Path) -> Self {
+        Self {
+            root_dir: root_dir.to_path_buf(),
+            state: Mutex::default(),
+        }
+    }
+
+    /// 追加报告中的备份；同一文件多次转换时保留每一次的备份
+    pub(crate) fn append(&self, report: &FileReport) {
+        let Some(entry) = BackupEntry::from_report(&self.root_dir, report) else {
+            return;
+        };
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        if state.error.is_some() {
+            return;
+        }
+        let result = state.write(&manifest_path(&self.root_dir), &entry);
+        if let Err(e) = result {
+            state.error = Some(e);
+        }
+    }
+
+    /// 返回写入清单时遇到的错误
+    pub(crate) fn finish(self) -> io::Result<()> {
+        let state = self
+            .state
+            .into_inner()
+            .unwrap_or_else(PoisonError::into_inner);
+        match state.error {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+}
+
+/// 相对 `root_dir` 的路径；不在 `root_dir` 下的路径（如命令行传入的其他目录中的文件）
+/// 相对于当前目录，转为绝对路径，以免 `restore` 时按 `--dir` 解析到错误的位置
+fn relative_to(root_dir: &Path, path: &Path) -> PathBuf {
+    match path.strip_prefix(root_dir) {
+        Ok(relative) => relative.to_path_buf(),
+        Err(_) => fs::canonicalize(path)
+            .or_else(|_| std::path::absolute(path))
+            .unwrap_or_else(|_| path.to_path_buf()),
+    }
+}
+
+/// `--backup-dir` 解析后的实际目录（相对路径基于 `--dir`）
+pub(crate) fn backup_root(config: &Config) -> Option<PathBuf> {
+    let dir = config.backup_dir.as_deref()?;
+    Some(crate::resolve_path_in_dir(Path::new(&config.dir), dir))
+}
+
+/// 规范化后的 `--backup-dir`，用于遍历时识别备份目录；目录尚不存在时返回 `None`
+pub(crate) fn canonical_backup_root(config: &Config) -> Option<PathBuf> {
+    fs::canonicalize(backup_root(config)?).ok()
+}
+
+/// 目录是否就是备份目录：规范化后比较，`--dir` 与 `--backup-dir` 一个写相对路径、一个写绝对路径时也能识别
+pub(crate) fn is_backup_root(dir: &Path, canonical_root: Option<&Path>) -> bool {
+    canonical_root.is_some_and(|root| fs::canonicalize(dir).is_ok_and(|dir| dir == root))
+}
+
+/// 在转换前复制原文件作为备份，返回备份路径。未指定 `--backup-dir` 时备份为同目录下的 `.bak` 文件，
+/// 否则按相对 `--dir` 的路径放到 `<backup-dir>/<时间戳>/` 下。已存在的备份不会被覆盖，改用带序号的文件名
+pub(crate) fn create_backup(file_path: &Path, config: &Config) -> io::Result<PathBuf> {
+    let base = match backup_root(config) {
+        Some(backup_root) => {
+            let root_dir = Path::new(&config.dir);
+            let relative: PathBuf = relative_to(root_dir, file_path)
+                .components()
+                .filter(|component| matches!(component, Component::Normal(_)))
+                .collect();
+            let mirrored = backup_root.join(run_timestamp()).join(relative);
+            if let Some(parent) = mirrored.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            mirrored
+        }
+        None => file_path.with_extension(format!(
+            "{}.bak",
+            file_path.extension().unwrap_or_default().to_string_lossy()
+        )),
+    };
+
+    let mut source = fs::File::open(file_path)?;
+    let permissions = source.metadata()?.permissions();
+    for n in 0.. {
+        let candidate = numbered(&base, n);
+        let mut backup = match fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&candidate)
+        {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        };
+        io::copy(&mut source, &mut backup)?;
+        backup.set_permissions(permissions)?;
+        return Ok(candidate);
+    }
+    unreachable!("backup file name candidates exhausted")
+}
+
+/// 第 n 个候选备份文件名：`n` 为 0 时不变，否则在最后一个扩展名前插入序号，如 `a.c.1.bak`
+fn numbered(base: &Path, n: usize) -> PathBuf {
+    if n == 0 {
+        return base.to_path_buf();
+    }
+    match (base.file_stem(), base.extension()) {
+        (Some(stem), Some(ext)) => base.with_file_name(format!(
+            "{}.{}.{}",
+            stem.to_string_lossy(),
+            n,
+            ext.to_string_lossy()
+        )),
+        _ => {
+            let mut name = base.as_os_str().to_os_string();
+            name.push(format!(".{}", n));
+            PathBuf::from(name)
+        }
+    }
+}
+
+/// 本次运行的 UTC 时间戳，格式为 `YYYYMMDD-HHMMSS`
+fn run_timestamp() -> &'static str {
+    RUN_TIMESTAMP.get_or_init(|| {
+        let secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let (days, rem) = (secs / 86_400, secs % 86_400);
+        let (year, month, day) = civil_from_days(days as i64);
+        format!(
+            "{:04}{:02}{:02}-{:02}{:02}{:02}",
+            year,
+            month,
+            day,
+            rem / 3600,
+            rem % 3600 / 60,
+            rem % 60
+        )
+    })
+}
+
+/// 1970-01-01 起的天数转换为公历年月日（Howard Hinnant 的 civil_from_days 算法）
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// 读取清单，没有清单时返回错误而不是静默成功
+fn load_existing(root_dir: &Path) -> io::Result<BackupManifest> {
+    let path = manifest_path(root_dir);
+    if !path.exists() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no backup manifest: {}", path.display()),
+        ));
+    }
+    BackupManifest::load(root_dir)
+}
+
+/// 按清单从新到旧用备份覆盖已转换的文件，多次转换的文件最终恢复为最早的原文件；
+/// 恢复成功的条目从清单中移除，`--dry-run` 时只列出将要恢复的文件
+pub fn restore_backups(config: &Config) -> io::Result<BackupRunResult> {
+    let root_dir = PathBuf::from(&config.dir);
+    let mut manifest = load_existing(&root_dir)?;
+    let mut result = BackupRunResult::default();
+    let mut remaining = Vec::new();
+
+    for entry in manifest.entries.into_iter().rev() {
+        let path = root_dir.join(&entry.path);
+        let backup = root_dir.join(&entry.backup);
+        let restored = if config.dry_run {
+            fs::metadata(&backup).map(|_| ())
+        } else {
+            restore_file(&backup, &path)
+        };
+        match restored {
+            Ok(()) => {
+                let message = match config.ui_lang() {
+                    UiLang::Zh => format!("已从 {} 恢复", backup.display()),
+                    UiLang::En => format!("restored from {}", backup.display()),
+                };
+                print_entry(config, "♻️", &path, &message);
+                result.processed.push(entry.clone());
+                if config.dry_run {
+                    remaining.push(entry);
+                }
+            }
+            Err(e) => {
+                result.errors.insert(path, e);
+                remaining.push(entry);
+            }
+        }
+    }
+
+    remaining.reverse();
+    manifest.entries = remaining;
+    if !config.dry_run {
+        manifest.save(&root_dir)?;
+    }
+    Ok(result)
+}
+
+/// 用备份的内容覆盖原文件后删除备份。与转换时一样经由 `AtomicWriter` 写入，
+/// 符号链接写入其指向的文件，有多个硬链接的文件直接写回，不会被替换成普通文件；
+/// 原文件已不存在时直接把备份移回原处
+fn restore_file(backup: &Path, path: &Path) -> io::Result<()> {
+    if fs::symlink_metadata(path).is_err() {
+        return move_file(backup, path);
+    }
+    let mut atomic = AtomicWriter::new(path)?;
+    io::copy(&mut fs::File::open(backup)?, atomic.file())?;
+    atomic.commit(false)?;
+    fs::remove_file(backup)
+}
+
+/// 移动文件；`--backup-dir` 与原文件不在同一文件系统时无法重命名，改为复制后删除
+fn move_file(from: &Path, to: &Path) -> io::Result<()> {
+    match fs::rename(from, to) {
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+            fs::copy(from, to)?;
+            fs::remove_file(from)
+        }
+        result => result,
+    }
+}
+
+/// 按清单删除备份文件并清空清单；备份已不存在的条目视为已删除
+pub fn clean_backups(config: &Config) -> io::Result<BackupRunResult> {
+    let root_dir = PathBuf::from(&config.dir);
+    let mut manifest = load_existing(&root_dir)?;
+    let mut result = BackupRunResult::default();
+    let mut remaining = Vec::new();
+
+    for entry in manifest.entries {
+        let backup = root_dir.join(&entry.backup);
+        let removed = if config.dry_run {
+            Ok(())
+        } else {
+            match fs::remove_file(&backup) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+                _ => Ok(()),
+            }
+        };
+        match removed {
+            Ok(()) => {
+                let message = tr(config, "已删除备份", "backup removed");
+                print_entry(config, "🗑️", &backup, message);
+                result.processed.push(entry.clone());
+                if config.dry_run {
+                    remaining.push(entry);
+                }
+            }
+            Err(e) => {
+                result.errors.insert(backup, e);
+                remaining.push(entry);
+            }
+        }
+    }
+
+    manifest.entries = remaining;
+    if !config.dry_run {
+        manifest.save(&root_dir)?;
+    }
+    Ok(result)
+}
+
+fn print_entry(config: &Config, prefix: &str, path: &Path, message: &str) {
+    let dry_run = if config.dry_run {
+        tr(config, "（试运行，未写入）", " (dry run)")
+    } else {
+        ""
+    };
+    println!("{} {}: {}{}", prefix, path.display(), message, dry_run);
+}
@@ -0,0 +1,65 @@
+use crate::{detect, utf32};
+use std::io::{self, Read};
+
+/// 判断是否为二进制文件时读取的文件开头字节数
+const SNIFF_BYTES: usize = 8 * 1024;
+
+/// 控制字符（不含制表符、换行、换页和 ESC）占比超过该值即视为二进制内容
+const MAX_CONTROL_RATIO: f64 = 0.1;
+
+/// 常见二进制格式的文件头（开头几 KB 内不一定出现 NUL）；只收录不会被误认为文本（包括 GBK 文本）开头的签名，
+/// Java class、Mach-O 等文件头本身就含有 NUL，由 NUL 检查覆盖
+const MAGIC_NUMBERS: &[&[u8]] = &[
+    b"\x89PNG\r\n\x1A\n",
+    b"\xFF\xD8\xFF",
+    b"GIF87a",
+    b"GIF89a",
+    b"%PDF-",
+    b"PK\x03\x04",
+    b"\x1F\x8B",
+    b"\xFD7zXZ\x00",
+    b"7z\xBC\xAF\x27\x1C",
+    b"Rar!\x1A\x07",
+    b"\x7FELF",
+    b"\x00asm",
+    b"SQLite format 3\x00",
+];
+
+/// 判断样本是否为二进制内容：已知文件头、出现 NUL 字节或控制字符过多。
+/// UTF-16/UTF-32 文本本身含有大量 NUL，先解码再按字符统计控制字符，
+/// 以免由小整数组成的数据文件被当作 UTF-16/UTF-32 文本
+pub(crate) fn looks_binary(sample: &[u8]) -> bool {
+    if MAGIC_NUMBERS.iter().any(|magic| sample.starts_with(magic)) {
+        return true;
+    }
+    if let Some(kind) = utf32::sniff(sample, sample.len() < SNIFF_BYTES) {
+        return too_many_controls(kind.chars(sample));
+    }
+    if let Some(encoding) = detect::sniff_utf16(sample) {
+        let (text, _) = encoding.decode_without_bom_handling(&sample[..sample.len() / 2 * 2]);
+        return too_many_controls(text.chars());
+    }
+    if sample.contains(&0) {
+        return true;
+    }
+    too_many_controls(sample.iter().map(|&b| char::from(b)))
+}
+
+/// 控制字符（不含制表符、换行、换页和 ESC）占比是否超过 `MAX_CONTROL_RATIO`
+fn too_many_controls(chars: impl Iterator<Item = char>) -> bool {
+    let (mut total, mut controls) = (0usize, 0usize);
+    for c in chars {
+        total += 1;
+        if (c < ' ' && !matches!(c, '\t' | '\n' | '\r' | '\x0C' | '\x1B')) || c == '\x7F' {
+            controls += 1;
+        }
+    }
+    total > 0 && controls as f64 / total as f64 > MAX_CONTROL_RATIO
+}
+
+/// 读取文件开头判断是否为二进制内容
+pub(crate) fn sniff_reader<R: Read>(reader: &mut R) -> io::Result<bool> {
+    let mut head = Vec::with_capacity(SNIFF_BYTES);
+    reader.take(SNIFF_BYTES as u64).read_to_end(&mut head)?;
+    Ok(looks_binary(&head))
+}
@@ -0,0 +1,727 @@
+use crate::eol::LineEndingCounter;
+use crate::{utf32, Config, EncodingCandidate, LineEndings};
+use chardetng::EncodingDetector;
+use encoding_rs::{
+    Decoder, DecoderResult, Encoding, BIG5, EUC_JP, EUC_KR, GB18030, GBK, SHIFT_JIS, UTF_16BE,
+    UTF_16LE, UTF_8,
+};
+use std::collections::HashSet;
+use std::io::{self, Read};
+use std::sync::OnceLock;
+
+/// 分块读取文件时每块的大小
+pub(crate) const CHUNK_SIZE: usize = 64 * 1024;
+
+/// 排除 UTF-8 后，至少读取这么多字节且 chardetng 已有把握时提前停止检测
+const MIN_DETECTION_BYTES: usize = 256 * 1024;
+
+/// 置信度只根据文件开头这么多字节的样本计算，避免对超大文件的每个候选编码都完整解码一遍
+const SCORING_BYTES: usize = MIN_DETECTION_BYTES;
+
+/// 常用字占比与常用字对占比之和达到该值即视为完全合理的中文文本
+const PLAUSIBLE_TEXT_SCORE: f64 = 0.8;
+
+/// 猜测编码的得分低于该值时视为解出的是乱码
+const IMPLAUSIBLE_SCORE: f64 = 0.3;
+
+/// 样本中非 ASCII 字符很少时置信度的收缩程度：n 个字符的置信度乘以 n / (n + 该值)，
+/// 只有一条中文注释（三四个汉字）的文件仍能达到默认阈值
+const SHORT_SAMPLE_WEIGHT: f64 = 0.25;
+
+/// 无论 `--from` 如何设置都参与置信度比较的中文编码（GBK 的解码与 GB18030 相同，合并计算）。
+/// 只有这些编码按常用汉字统计文本合理度；其他多字节编码只看严格解码是否成功，单字节编码另按字母占比和大小写统计
+const CHINESE_CANDIDATES: [&Encoding; 2] = [GB18030, BIG5];
+
+/// `--candidates` 总是列出的编码：即使不在 `--from` 中，也可能是被误判的真实编码
+const LISTED_CANDIDATES: [&Encoding; 6] = [UTF_8, GB18030, BIG5, SHIFT_JIS, EUC_JP, EUC_KR];
+
+/// 单字节编码解码出的非 ASCII 字符中，除字母外也视为正常文本的标点和符号
+const SINGLE_BYTE_PUNCTUATION: &str = "‘’‚“”„–—…•€°«»·§©®™\u{A0}";
+
+/// 候选编码示例行最多保留的字符数
+const SAMPLE_CHARS: usize = 60;
+
+/// 识别字节内容的编码：合法 UTF-8 直接返回，否则使用 chardetng 猜测
+pub fn detect_encoding(content: &[u8], config: &Config) -> (&'static Encoding, f64) {
+    let mut detection = Detection::new(config);
+    detection.feed(content);
+    detection.finish(true)
+}
+
+/// 分块读取并识别编码，内存占用与文件大小无关；
+/// 已排除 UTF-8 且 chardetng 有把握时提前停止读取
+pub fn detect_encoding_reader<R: Read>(
+    reader: &mut R,
+    config: &Config,
+) -> io::Result<(&'static Encoding, f64)> {
+    let detected = detect_file(reader, config)?;
+    Ok((detected.encoding, detected.confidence))
+}
+
+/// 识别文件编码的结果，附带检测过程中顺便得到的 BOM 和换行符信息
+pub(crate) struct FileDetection {
+    pub(crate) encoding: &'static Encoding,
+    pub(crate) confidence: f64,
+    /// 文件是否以识别出的编码的 BOM 开头
+    pub(crate) bom: bool,
+    /// 换行符统计；只有读完了整个文件且编码与 ASCII 兼容时才可用（外层为 `None` 表示需要另行扫描）
+    pub(crate) line_endings: Option<Option<LineEndings>>,
+}
+
+/// 同 `detect_encoding_reader`，读取的同时统计换行符，读完整个文件时无需再扫描一遍
+pub(crate) fn detect_file<R: Read>(reader: &mut R, config: &Config) -> io::Result<FileDetection> {
+    let mut detection = Detection::new(config);
+    let mut buf = vec![0u8; CHUNK_SIZE];
+    loop {
+        let n = read_chunk(reader, &mut buf)?;
+        let at_eof = n == 0;
+        if !at_eof {
+            detection.feed(&buf[..n]);
+        }
+        if at_eof || detection.settled() {
+            let bom = detection.bom;
+            let line_endings = std::mem::take(&mut detection.line_endings);
+            let (encoding, confidence) = detection.finish(at_eof);
+            return Ok(FileDetection {
+                encoding,
+                confidence,
+                bom: bom == Some(encoding),
+                line_endings: (at_eof && encoding.is_ascii_compatible())
+                    .then(|| line_endings.finish()),
+            });
+        }
+    }
+}
+
+/// 只根据最多 `limit` 字节的前缀识别编码，并返回已读取的前缀，
+/// 用于无法回读的输入（如标准输入）
+pub(crate) fn detect_prefix<R: Read>(
+    reader: &mut R,
+    config: &Config,
+    limit: usize,
+) -> io::Result<(&'static Encoding, f64, Vec<u8>)> {
+    let mut prefix = Vec::new();
+    reader.take(limit as u64).read_to_end(&mut prefix)?;
+    let at_eof = prefix.len() < limit;
+
+    let mut detection = Detection::new(config);
+    detection.feed(&prefix);
+    let (encoding, confidence) = detection.finish(at_eof);
+    Ok((encoding, confidence, prefix))
+}
+
+/// 对文件开头的样本逐个尝试候选编码，按得分从高到低返回前 `count` 个，得分相同时 chardetng 的猜测排在前面；
+/// 每个候选附带解码错误数和第一行含非 ASCII 字符的解码结果，便于人工判断误判原因
+pub fn rank_candidates<R: Read>(
+    reader: &mut R,
+    config: &Config,
+    count: usize,
+) -> io::Result<Vec<EncodingCandidate>> {
+    let mut sample = Vec::new();
+    reader.take(SCORING_BYTES as u64).read_to_end(&mut sample)?;
+    let at_eof = sample.len() < SCORING_BYTES;
+    let mut gb18030 = Gb18030Scanner::default();
+    gb18030.feed(&sample);
+
+    // chardetng 的猜测（如 windows-1252）即使不在固定列表和 --from 中也要列出
+    let mut detection = Detection::new(config);
+    detection.feed(&sample);
+    let guess = scoring_encoding(detection.finish(at_eof).0);
+    let mut encodings = LISTED_CANDIDATES.to_vec();
+    for encoding in config.source_encodings()?.into_iter().chain([guess]) {
+        let encoding = scoring_encoding(encoding);
+        if !encodings.contains(&encoding) {
+            encodings.push(encoding);
+        }
+    }
+
+    let candidates: Vec<CandidateScore> = encodings
+        .into_iter()
+        .map(|encoding| {
+            let mut candidate = CandidateScore::new(encoding);
+            candidate.feed(&sample, at_eof);
+            candidate
+        })
+        .collect();
+    let mut ranked: Vec<(bool, EncodingCandidate)> = candidates
+        .iter()
+        .map(|candidate| {
+            // GBK 与 GB18030 解码结果相同，只有出现四字节序列时才显示为 GB18030
+            let label = if candidate.encoding == GB18030 && !gb18030.found {
+                GBK
+            } else {
+                candidate.encoding
+            };
+            let ranked = EncodingCandidate {
+                encoding: label.name().to_lowercase(),
+                score: round_score(contested_score(candidate, &candidates)),
+                errors: candidate.errors,
+                sample: candidate.sample(),
+            };
+            (candidate.encoding == guess, ranked)
+        })
+        .collect();
+    ranked.sort_by(|(a_guess, a), (b_guess, b)| {
+        b.score
+            .total_cmp(&a.score)
+            .then(b_guess.cmp(a_guess))
+            .then(a.errors.cmp(&b.errors))
+    });
+    Ok(ranked
+        .into_iter()
+        .take(count)
+        .map(|(_, candidate)| candidate)
+        .collect())
+}
+
+/// 尽量读满缓冲区，返回 0 表示已到文件末尾
+pub(crate) fn read_chunk<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+/// 增量编码检测状态
+struct Detection<'a> {
+    config: &'a Config,
+    detector: EncodingDetector,
+    utf8: Utf8Validator,
+    gb18030: Gb18030Scanner,
+    candidates: Vec<CandidateScore>,
+    /// 根据 BOM 或空字节分布识别出的 UTF-16 编码（UTF-8 BOM 本身是合法 UTF-8，交给 UTF-8 校验处理）
+    utf16: Option<&'static Encoding>,
+    /// 开头 BOM 对应的编码
+    bom: Option<&'static Encoding>,
+    line_endings: LineEndingCounter,
+    /// 参与打分的样本，用于给不在候选列表中的猜测编码打分
+    sample: Vec<u8>,
+    fed: usize,
+}
+
+impl<'a> Detection<'a> {
+    fn new(config: &'a Config) -> Self {
+        let mut encodings: Vec<&'static Encoding> = CHINESE_CANDIDATES.to_vec();
+        for encoding in config.source_encodings().unwrap_or_default() {
+            let encoding = scoring_encoding(encoding);
+            if encoding != UTF_8 && !encodings.contains(&encoding) {
+                encodings.push(encoding);
+            }
+        }
+
+        Self {
+            config,
+            detector: EncodingDetector::new(),
+            utf8: Utf8Validator::default(),
+            gb18030: Gb18030Scanner::default(),
+            candidates: encodings.into_iter().map(CandidateScore::new).collect(),
+            utf16: None,
+            bom: None,
+            line_endings: LineEndingCounter::default(),
+            sample: Vec::new(),
+            fed: 0,
+        }
+    }
+
+    fn feed(&mut self, chunk: &[u8]) {
+        if self.fed == 0 {
+            self.utf16 = sniff_utf16(chunk);
+            self.bom = Encoding::for_bom(chunk).map(|(encoding, _)| encoding);
+        }
+        self.line_endings.feed(chunk);
+        self.detector.feed(chunk, false);
+        self.utf8.feed(chunk);
+        self.gb18030.feed(chunk);
+
+        let sample = &chunk[..chunk.len().min(SCORING_BYTES.saturating_sub(self.fed))];
+        if !sample.is_empty() {
+            for candidate in &mut self.candidates {
+                candidate.feed(sample, false);
+            }
+            self.sample.extend_from_slice(sample);
+        }
+        self.fed += chunk.len();
+    }
+
+    fn tld(&self) -> Option<&[u8]> {
+        self.config.tld.as_deref().map(str::as_bytes)
+    }
+
+    /// 已识别为 UTF-16，或已确定不是 UTF-8、读取量足够且 chardetng 有把握时，无需继续读取
+    fn settled(&self) -> bool {
+        self.utf16.is_some()
+            || (!self.utf8.valid
+                && self.fed >= MIN_DETECTION_BYTES
+                && self.detector.guess_assess(self.tld(), false).1)
+    }
+
+    /// `at_eof` 为假表示只检测了前缀，末尾不完整的 UTF-8 序列不视为错误
+    fn finish(mut self, at_eof: bool) -> (&'static Encoding, f64) {
+        if let Some(encoding) = self.utf16 {
+            return (encoding, 1.0);
+        }
+        if self.utf8.is_valid(at_eof) {
+            // 纯 ASCII 的内容已经是任何 ASCII 兼容的目标编码（如 --to gbk），无需转换
+            if at_eof && self.utf8.ascii {
+                if let Ok(target) = self.config.target_encoding() {
+                    if target.is_ascii_compatible() {
+                        return (target, 1.0);
+                    }
+                }
+            }
+            return (UTF_8, 1.0);
+        }
+
+        if at_eof {
+            self.detector.feed(&[], true);
+            if self.fed <= SCORING_BYTES {
+                for candidate in &mut self.candidates {
+                    candidate.feed(&[], true);
+                }
+            }
+        }
+        let mut encoding = self.detector.guess(self.tld(), false);
+        // TLD 提示（默认 cn）会让 chardetng 把较短的日文、繁体中文文本也猜成 GBK，
+        // 猜测结果解出的是乱码时改用不带提示的猜测
+        if self.tld().is_some() && self.implausible(encoding, at_eof) {
+            let unhinted = self.detector.guess(None, false);
+            if !self.implausible(unhinted, at_eof) {
+                encoding = unhinted;
+            }
+        }
+        if encoding == GBK && self.gb18030.found {
+            encoding = GB18030;
+        }
+
+        (encoding, self.confidence(encoding, at_eof))
+    }
+
+    /// 对 `encoding` 的严格解码统计调用 `f`；不在候选列表中的编码（如单字节编码）用保留的样本单独统计
+    fn with_candidate<T>(
+        &self,
+        encoding: &'static Encoding,
+        at_eof: bool,
+        f: impl FnOnce(&CandidateScore) -> T,
+    ) -> T {
+        let scoring = scoring_encoding(encoding);
+        match self.candidates.iter().find(|c| c.encoding == scoring) {
+            Some(candidate) => f(candidate),
+            None => {
+                let mut candidate = CandidateScore::new(scoring);
+                candidate.feed(&self.sample, at_eof && self.fed <= SCORING_BYTES);
+                f(&candidate)
+            }
+        }
+    }
+
+    /// 候选编码严格解码得到的文本是否明显不合理
+    fn implausible(&self, encoding: &'static Encoding, at_eof: bool) -> bool {
+        self.with_candidate(encoding, at_eof, |c| c.score() < IMPLAUSIBLE_SCORE)
+    }
+
+    /// 综合候选编码的严格解码结果计算置信度：
+    /// 猜测编码自身得分越高、中文候选编码越难解出同样合理的文本，置信度越高；
+    /// 样本中的非 ASCII 字符越少，置信度越向下收缩。
+    /// 日文、韩文等编码的得分无法与按汉字统计的得分直接比较，只拿中文候选编码作为对手
+    fn confidence(&self, encoding: &'static Encoding, at_eof: bool) -> f64 {
+        let confidence = self.with_candidate(encoding, at_eof, |own| {
+            let evidence = own.chars as f64 / (own.chars as f64 + SHORT_SAMPLE_WEIGHT);
+            contested_score(own, &self.candidates) * evidence
+        });
+        round_score(confidence)
+    }
+}
+
+/// 考虑竞争后的得分：其他中文候选编码也能解出同样合理的文本时，得分最多减半
+fn contested_score(own: &CandidateScore, candidates: &[CandidateScore]) -> f64 {
+    let score = own.score();
+    if score <= 0.0 {
+        return 0.0;
+    }
+    let rivalry = (chinese_runner_up(own.encoding, candidates) / score).min(1.0);
+    score * (1.0 - 0.5 * rivalry * rivalry)
+}
+
+/// 除 `encoding` 外中文候选编码的最高得分
+fn chinese_runner_up(encoding: &'static Encoding, candidates: &[CandidateScore]) -> f64 {
+    candidates
+        .iter()
+        .filter(|c| c.chinese && c.encoding != encoding)
+        .map(CandidateScore::score)
+        .fold(0.0, f64::max)
+}
+
+/// 根据 BOM 或空字节分布识别 UTF-16：没有 BOM 时，要求 ASCII 字符的高位字节 0x00 集中出现在
+/// 同一侧（不少于码元数的 30%），另一侧几乎没有 0x00，并且样本能按该字节序严格解码。
+/// UTF-32 的 BOM 以 UTF-16LE 的 BOM 开头，需要排除
+pub(crate) fn sniff_utf16(sample: &[u8]) -> Option<&'static Encoding> {
+    if utf32::sniff(sample, false).is_some() {
+        return None;
+    }
+    if let Some((encoding, _)) = Encoding::for_bom(sample) {
+        return (encoding == UTF_16LE || encoding == UTF_16BE).then_some(encoding);
+    }
+
+    let units = sample.len() / 2;
+    if units < 2 {
+        return None;
+    }
+    let sample = &sample[..units * 2];
+    let zeros = |offset: usize| {
+        sample
+            .iter()
+            .skip(offset)
+            .step_by(2)
+            .filter(|&&b| b == 0)
+            .count()
+    };
+    let (even, odd) = (zeros(0), zeros(1));
+    let encoding = if odd * 10 >= units * 3 && even * 20 < units {
+        UTF_16LE
+    } else if even * 10 >= units * 3 && odd * 20 < units {
+        UTF_16BE
+    } else {
+        return None;
+    };
+
+    // 样本末尾可能截断在代理对中间，严格解码时忽略最后一个码元
+    let body = &sample[..sample.len() - 2];
+    encoding
+        .decode_without_bom_handling_and_without_replacement(body)
+        .is_some()
+        .then_some(encoding)
+}
+
+/// 保留三位小数，避免报告中出现过长的浮点数
+fn round_score(score: f64) -> f64 {
+    (score * 1000.0).round() / 1000.0
+}
+
+/// GBK 的解码规则与 GB18030 相同，打分时合并为同一个候选编码
+fn scoring_encoding(encoding: &'static Encoding) -> &'static Encoding {
+    if encoding == GBK {
+        GB18030
+    } else {
+        encoding
+    }
+}
+
+/// 单个候选编码对样本的严格解码统计
+struct CandidateScore {
+    encoding: &'static Encoding,
+    /// 是否为中文编码，决定是否按常用汉字统计文本合理度
+    chinese: bool,
+    /// 是否为单字节编码，决定是否按字母占比和大小写统计文本合理度
+    single_byte: bool,
+    decoder: Decoder,
+    text: String,
+    /// 非法字节序列的数量
+    errors: usize,
+    /// 解码得到的非 ASCII 字符数量
+    chars: usize,
+    /// 其中属于常用汉字或中文标点的数量
+    common: usize,
+    /// 相邻非 ASCII 字符对的数量
+    pairs: usize,
+    /// 其中两个字符都常用的字符对数量
+    common_pairs: usize,
+    /// 上一个字符的状态：`None` 表示 ASCII 或样本开头，否则表示是否常用
+    previous: Option<bool>,
+    /// 单字节编码：相邻且至少一个是非 ASCII 字符的字母对数量
+    letter_pairs: usize,
+    /// 其中小写字母后紧跟大写字母的数量（正常文本的单词中间很少出现）
+    case_flips: usize,
+    /// 上一个字符，用于统计字母对
+    last: Option<char>,
+    /// 正在解码的当前行（最多 `SAMPLE_CHARS` 个字符）
+    line: String,
+    line_chars: usize,
+    line_has_non_ascii: bool,
+    /// 第一行含非 ASCII 字符的解码结果
+    sample: Option<String>,
+}
+
+impl CandidateScore {
+    fn new(encoding: &'static Encoding) -> Self {
+        Self {
+            encoding,
+            chinese: CHINESE_CANDIDATES.contains(&encoding),
+            single_byte: encoding.is_single_byte(),
+            decoder: encoding.new_decoder_without_bom_handling(),
+            text: String::with_capacity(CHUNK_SIZE * 3 + 16),
+            errors: 0,
+            chars: 0,
+            common: 0,
+            pairs: 0,
+            common_pairs: 0,
+            previous: None,
+            letter_pairs: 0,
+            case_flips: 0,
+            last: None,
+            line: String::new(),
+            line_chars: 0,
+            line_has_non_ascii: false,
+            sample: None,
+        }
+    }
+
+    fn feed(&mut self, chunk: &[u8], last: bool) {
+        let mut src = chunk;
+        let mut text = std::mem::take(&mut self.text);
+        loop {
+            text.clear();
+            let (result, read) = self
+                .decoder
+                .decode_to_string_without_replacement(src, &mut text, last);
+            src = &src[read..];
+            for c in text.chars() {
+                self.count(c);
+            }
+            match result {
+                DecoderResult::InputEmpty => break,
+                DecoderResult::OutputFull => {}
+                DecoderResult::Malformed(_, _) => {
+                    self.errors += 1;
+                    self.previous = None;
+                    self.last = None;
+                    if self.sample.is_none() {
+                        self.record_sample(char::REPLACEMENT_CHARACTER);
+                    }
+                }
+            }
+        }
+        self.text = text;
+    }
+
+    fn count(&mut self, c: char) {
+        if self.sample.is_none() {
+            self.record_sample(c);
+        }
+        if self.single_byte {
+            self.count_letter_pair(c);
+        }
+        if c.is_ascii() {
+            self.previous = None;
+            return;
+        }
+        let common = if self.single_byte {
+            c.is_alphabetic() || SINGLE_BYTE_PUNCTUATION.contains(c)
+        } else {
+            is_common_chinese(c)
+        };
+        self.chars += 1;
+        self.common += usize::from(common);
+        if let Some(previous) = self.previous {
+            self.pairs += 1;
+            self.common_pairs += usize::from(previous && common);
+        }
+        self.previous = Some(common);
+    }
+
+    /// 统计含非 ASCII 字符的相邻字母对中小写后紧跟大写的情况：
+    /// 多字节文本被误当作单字节编码解码时，得到的字母大小写近乎随机
+    fn count_letter_pair(&mut self, c: char) {
+        if let Some(last) = self.last {
+            if last.is_alphabetic() && c.is_alphabetic() && !(last.is_ascii() && c.is_ascii()) {
+                self.letter_pairs += 1;
+                self.case_flips += usize::from(last.is_lowercase() && c.is_uppercase());
+            }
+        }
+        self.last = Some(c);
+    }
+
+    /// 逐字符记录当前行，遇到换行时若该行含非 ASCII 字符则作为示例行
+    fn record_sample(&mut self, c: char) {
+        if c == '\n' {
+            if self.line_has_non_ascii {
+                self.sample = Some(std::mem::take(&mut self.line));
+            }
+            self.line.clear();
+            self.line_chars = 0;
+            self.line_has_non_ascii = false;
+            return;
+        }
+        self.line_has_non_ascii |= !c.is_ascii();
+        if self.line_chars < SAMPLE_CHARS {
+            self.line
+                .push(if c.is_control() && c != '\t' { ' ' } else { c });
+            self.line_chars += 1;
+        }
+    }
+
+    fn sample(&self) -> String {
+        match &self.sample {
+            Some(sample) => sample.trim().to_string(),
+            None if self.line_has_non_ascii => self.line.trim().to_string(),
+            None => String::new(),
+        }
+    }
+
+    /// 得分 = 合法多字节序列占比 × 文本合理度，范围 0~1。
+    /// 合理度由常用字占比与常用字对（二元组）占比相加得到，正常中文文本两者之和通常超过
+    /// `PLAUSIBLE_TEXT_SCORE`，错误解码得到的乱码则远低于它。
+    /// 非中文编码出现任何非法序列即得 0 分（严格解码无法转换）；单字节编码几乎总能解码，
+    /// 另按字母和常见标点的占比、单词中间大小写跳变的比例计算合理度
+    fn score(&self) -> f64 {
+        let total = self.chars + self.errors;
+        if total == 0 {
+            return 0.0;
+        }
+        if !self.chinese {
+            if self.errors > 0 {
+                return 0.0;
+            }
+            if !self.single_byte {
+                return 1.0;
+            }
+            let letters = self.common as f64 / self.chars as f64;
+            let flips = if self.letter_pairs == 0 {
+                0.0
+            } else {
+                self.case_flips as f64 / self.letter_pairs as f64
+            };
+            return letters * (1.0 - 2.0 * flips).max(0.0);
+        }
+        let validity = self.chars as f64 / total as f64;
+        let unigram = self.common as f64 / self.chars.max(1) as f64;
+        let bigram = if self.pairs == 0 {
+            unigram
+        } else {
+            self.common_pairs as f64 / self.pairs as f64
+        };
+        validity * ((unigram + bigram) / PLAUSIBLE_TEXT_SCORE).min(1.0)
+    }
+}
+
+/// 判断字符是否为常用汉字或中文标点（含全角 ASCII）。
+/// 用错误的编码解码时，得到的多为生僻字或无关字符，这一比例会明显下降。
+fn is_common_chinese(c: char) -> bool {
+    static FREQUENT: OnceLock<HashSet<char>> = OnceLock::new();
+    ('\u{FF01}'..='\u{FF5E}').contains(&c)
+        || CHINESE_PUNCTUATION.contains(c)
+        || FREQUENT
+            .get_or_init(|| {
+                FREQUENT_SIMPLIFIED
+                    .chars()
+                    .chain(FREQUENT_TRADITIONAL.chars())
+                    .collect()
+            })
+            .contains(&c)
+}
+
+/// 常用简体汉字（按字频挑选，含编程注释常见用字），正常中文文本中的多数字符都属于这个集合
+const FREQUENT_SIMPLIFIED: &str = "\
+    的一是不了在人有我他这个们中来上大为和国地到以说时要就出会可也你对生能而子那得于\
+    着下自之年过发后作里用道行所然家种事成方多经么去法学如都同现当没动面起看定天分还\
+    进好小部其些主样理心她本前开但因只从想实日军者意无力它与长把机十民第公此已工使情\
+    明性知全三又关点正业外将两高间由问很最重并物手应战向头文体政美相见被利什二等产或\
+    新己制身果加西斯月话合回特代内信表化老给世位次度门任常先海通教儿原东声提立及比员\
+    解水名真论处走义各入几口认条平系气题活尔更别打女变四神总何电数安少报才结反受目太\
+    量再感建务做接必场件计管期市直德资命山金指克许统区保至队形社便空决治展马科司五基\
+    眼书非则听白却界达光放强即像难且权思王象完设式色路记南品住告类求据程北边死张该交\
+    规万取拉格望觉术领共确传师观清今切院让识候带导争运笑飞风步改收根干造言联持组每济\
+    车亲极林服快办议往元英士证近失转夫令准布始怎呢存未远叫台单影具罗字爱击流备兵连调\
+    深商算质团集百需价花党华城石级整府离况亚请技际约示复病息究线似官火断精满支视消越\
+    器容照须九增研写称企八功吗包片史委乎查轻易早曾除农找装广显函返串址链循储删码值型\
+    参针错误警试编译库注释版配置读插换输缓默初符浮构枚举块测";
+
+/// 上表中与简体字形不同的繁体字
+const FREQUENT_TRADITIONAL: &str = "\
+    這個們來為國說時會對於著過發後裡種經麼學現當沒動還進樣開從實軍無與長機關點業將兩\
+    間問並應戰頭體見產製話內給門兒東聲員論處義幾認條氣題爾別變總電數報結務場計資許統\
+    區隊決馬書則聽卻達強難權設記類據邊張該規萬覺術領確傳師觀讓識帶導爭運飛風幹聯組濟\
+    車親極辦議證轉準遠單羅愛擊備連調質團價黨華級離況亞請際約復線斷滿視須寫稱嗎輕農裝\
+    廣顯鏈儲刪碼參針錯誤試編譯庫註釋讀換輸緩構舉塊測";
+
+/// 与字频无关、总是视为常见的中文标点
+const CHINESE_PUNCTUATION: &str = "，。、；：？！“”‘’（）《》〈〉【】「」『』…—·　";
+
+/// 跨块校验 UTF-8，块末尾被截断的多字节序列留到下一块继续校验
+struct Utf8Validator {
+    valid: bool,
+    /// 目前为止是否只出现过 ASCII 字节
+    ascii: bool,
+    pending: Vec<u8>,
+}
+
+impl Default for Utf8Validator {
+    fn default() -> Self {
+        Self {
+            valid: true,
+            ascii: true,
+            pending: Vec::new(),
+        }
+    }
+}
+
+impl Utf8Validator {
+    fn feed(&mut self, chunk: &[u8]) {
+        if !self.valid {
+            return;
+        }
+        self.ascii &= chunk.is_ascii();
+        let joined;
+        let data = if self.pending.is_empty() {
+            chunk
+        } else {
+            joined = [self.pending.as_slice(), chunk].concat();
+            &joined
+        };
+        match std::str::from_utf8(data) {
+            Ok(_) => self.pending.clear(),
+            Err(e) if e.error_len().is_none() => self.pending = data[e.valid_up_to()..].to_vec(),
+            Err(_) => self.valid = false,
+        }
+    }
+
+    fn is_valid(&self, at_eof: bool) -> bool {
+        self.valid && (!at_eof || self.pending.is_empty())
+    }
+}
+
+/// 查找 GB18030 四字节序列（GBK 无法表示的字符，如扩展 B 区汉字、emoji），
+/// 块末尾不足四字节的候选序列留到下一块继续判断
+#[derive(Default)]
+struct Gb18030Scanner {
+    found: bool,
+    carry: Vec<u8>,
+}
+
+impl Gb18030Scanner {
+    fn feed(&mut self, chunk: &[u8]) {
+        if self.found {
+            return;
+        }
+        let mut data = std::mem::take(&mut self.carry);
+        data.extend_from_slice(chunk);
+
+        let mut i = 0;
+        while i < data.len() {
+            let lead = data[i];
+            if !(0x81..=0xFE).contains(&lead) {
+                i += 1;
+                continue;
+            }
+            match data.get(i + 1..i + 4) {
+                Some(&[second, third, fourth]) => {
+                    if second.is_ascii_digit()
+                        && (0x81..=0xFE).contains(&third)
+                        && fourth.is_ascii_digit()
+                    {
+                        self.found = true;
+                        return;
+                    }
+                    i += 2;
+                }
+                _ => break,
+            }
+        }
+        self.carry = data[i..].to_vec();
+    }
+}
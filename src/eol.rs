@@ -0,0 +1,195 @@
+use crate::detect::{read_chunk, CHUNK_SIZE};
+use crate::EolOption;
+use encoding_rs::{CoderResult, Encoding};
+use serde::Serialize;
+use std::io::{self, Read};
+
+/// 文件中各种换行符的数量
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct LineEndings {
+    pub crlf: usize,
+    pub lf: usize,
+    /// 单独的 `\r`（旧版 Mac 换行）
+    pub cr: usize,
+}
+
+impl LineEndings {
+    /// 是否混用了不止一种换行符
+    pub fn is_mixed(&self) -> bool {
+        [self.crlf, self.lf, self.cr]
+            .iter()
+            .filter(|&&n| n > 0)
+            .count()
+            > 1
+    }
+
+    /// 按 `--eol` 是否需要改写换行符；单独的 `\r` 不做处理
+    pub fn needs_fix(&self, eol: EolOption) -> bool {
+        match eol {
+            EolOption::Keep => false,
+            EolOption::Lf => self.crlf > 0,
+            EolOption::Crlf => self.lf > 0,
+        }
+    }
+
+    fn total(&self) -> usize {
+        self.crlf + self.lf + self.cr
+    }
+}
+
+/// 分块统计换行符，`\r\n` 跨块时也只计一次。ASCII 兼容的编码直接扫描字节，
+/// UTF-16 等编码先解码再统计，避免把多字节字符中的 0x0A/0x0D 当成换行符
+pub fn scan_line_endings<R: Read>(
+    reader: &mut R,
+    encoding: &'static Encoding,
+) -> io::Result<Option<LineEndings>> {
+    let mut counter = LineEndingCounter::default();
+    let mut decoder = (!encoding.is_ascii_compatible()).then(|| encoding.new_decoder());
+    let mut buf = vec![0u8; CHUNK_SIZE];
+    let mut text = String::with_capacity(CHUNK_SIZE * 3 + 16);
+    loop {
+        let n = read_chunk(reader, &mut buf)?;
+        let last = n == 0;
+        match decoder.as_mut() {
+            Some(decoder) => {
+                let mut src = &buf[..n];
+                loop {
+                    text.clear();
+                    let (result, read, _) = decoder.decode_to_string(src, &mut text, last);
+                    counter.feed(text.as_bytes());
+                    src = &src[read..];
+                    if result == CoderResult::InputEmpty {
+                        break;
+                    }
+                }
+            }
+            None => counter.feed(&buf[..n]),
+        }
+        if last {
+            break;
+        }
+    }
+    Ok(counter.finish())
+}
+
+/// 增量统计换行符，只适用于 ASCII 兼容的编码或解码后的文本
+#[derive(Default)]
+pub(crate) struct LineEndingCounter {
+    counts: LineEndings,
+    pending_cr: bool,
+}
+
+impl LineEndingCounter {
+    pub(crate) fn feed(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            match (self.pending_cr, byte) {
+                (true, b'\n') => self.counts.crlf += 1,
+                (true, _) => self.counts.cr += 1,
+                (false, b'\n') => self.counts.lf += 1,
+                _ => {}
+            }
+            self.pending_cr = byte == b'\r';
+        }
+    }
+
+    pub(crate) fn finish(mut self) -> Option<LineEndings> {
+        if self.pending_cr {
+            self.counts.cr += 1;
+        }
+        (self.counts.total() > 0).then_some(self.counts)
+    }
+}
+
+/// 按 `--eol` 改写解码后的文本中的换行符，可以分段输入；
+/// 段末尾的 `\r` 会暂存到下一段，以免把跨段的 `\r\n` 当成两个换行
+pub(crate) struct EolNormalizer {
+    eol: EolOption,
+    pending_cr: bool,
+    /// 已改写的换行符数量
+    pub(crate) changed: usize,
+}
+
+impl EolNormalizer {
+    pub(crate) fn new(eol: EolOption) -> Self {
+        Self {
+            eol,
+            pending_cr: false,
+            changed: 0,
+        }
+    }
+
+    /// 返回改写后的文本；`--eol keep` 时原样返回，不复制
+    pub(crate) fn apply<'a>(&mut self, text: &'a str, buf: &'a mut String) -> &'a str {
+        if self.eol == EolOption::Keep {
+            return text;
+        }
+        buf.clear();
+        let bytes = text.as_bytes();
+        let mut start = 0;
+        if self.pending_cr {
+            self.pending_cr = false;
+            if bytes.first() == Some(&b'\n') {
+                self.push_newline(true, buf);
+                start = 1;
+            } else {
+                buf.push('\r');
+            }
+        }
+
+        let mut i = start;
+        while i < bytes.len() {
+            match bytes[i] {
+                b'\r' if i + 1 == bytes.len() => {
+                    buf.push_str(&text[start..i]);
+                    self.pending_cr = true;
+                    start = bytes.len();
+                }
+                b'\r' if bytes[i + 1] == b'\n' => {
+                    buf.push_str(&text[start..i]);
+                    self.push_newline(true, buf);
+                    i += 1;
+                    start = i + 1;
+                }
+                b'\n' => {
+                    buf.push_str(&text[start..i]);
+                    self.push_newline(false, buf);
+                    start = i + 1;
+                }
+                _ => {}
+            }
+            i += 1;
+        }
+        buf.push_str(&text[start..]);
+        buf
+    }
+
+    /// 输入结束时输出暂存的 `\r`
+    pub(crate) fn finish(&mut self) -> &'static str {
+        if std::mem::take(&mut self.pending_cr) {
+            "\r"
+        } else {
+            ""
+        }
+    }
+
+    fn push_newline(&mut self, crlf: bool, buf: &mut String) {
+        let wanted_crlf = match self.eol {
+            EolOption::Keep => crlf,
+            EolOption::Lf => false,
+            EolOption::Crlf => true,
+        };
+        if wanted_crlf != crlf {
+            self.changed += 1;
+        }
+        buf.push_str(if wanted_crlf { "\r\n" } else { "\n" });
+    }
+}
+
+/// 一次性改写完整文本中的换行符
+pub(crate) fn normalize(text: &str, eol: EolOption) -> String {
+    let mut normalizer = EolNormalizer::new(eol);
+    let mut buf = String::with_capacity(text.len());
+    let mut out = normalizer.apply(text, &mut buf).to_string();
+    out.push_str(normalizer.finish());
+    out
+}
@@ -1,19 +1,59 @@
-use chardetng::EncodingDetector;
-use clap::{Parser, ValueEnum};
-use encoding::all::GBK;
-use encoding::{DecoderTrap, Encoding};
+use clap::{Parser, Subcommand, ValueEnum};
+use encoding_rs::{Encoding, GBK, UTF_16BE, UTF_16LE, UTF_8};
+use globset::GlobBuilder;
 use ignore::gitignore::{Gitignore, GitignoreBuilder};
-use std::collections::HashMap;
+use ignore::WalkBuilder;
+use serde::{Deserialize, Serialize};
+use similar::{ChangeTag, TextDiff};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::env;
 use std::fs;
-use std::io::{self, Read, Write};
+use std::fmt::Write as _;
+use std::io::{self, Read, Seek, Write};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+
+mod backup;
+mod binary;
+mod detect;
+mod eol;
+mod mixed;
+mod project_config;
+mod report;
+mod transcode;
+mod utf32;
+
+pub use backup::{
+    clean_backups, manifest_path, restore_backups, BackupEntry, BackupManifest, BackupRunResult,
+    MANIFEST_FILE,
+};
+pub use detect::{detect_encoding, detect_encoding_reader, rank_candidates};
+pub use eol::{scan_line_endings, LineEndings};
+pub use mixed::EncodingSegment;
+pub use project_config::{PathOverride, PathOverrides};
+pub use report::{EncodingCandidate, FileAction, FileReport};
+use transcode::{encode_text, transcode};
+use utf32::{Utf32, Utf32Reader};
+
+/// 标准输入模式下用于识别编码的最大前缀字节数
+const STREAM_DETECTION_LIMIT: usize = 1024 * 1024;
 
 /// GBK 转 UTF-8 工具（自动识别编码）
 #[derive(Parser, Debug)]
 #[command(author, version, about)]
 pub struct Config {
-    #[arg(short = 'd', long, default_value = "./", help = "要扫描的目录路径")]
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    #[arg(
+        value_name = "PATH",
+        help = "要处理的文件、目录或 glob 模式（如 src/**/*.c、pre-commit 传入的暂存文件）；指定后不再遍历 --dir，扩展名和忽略规则仍然生效；单独的 - 表示从标准输入读取并输出到标准输出"
+    )]
+    pub paths: Vec<PathBuf>,
+
+    #[arg(short = 'd', long, global = true, default_value = "./", help = "要扫描的目录路径")]
     pub dir: String,
 
     #[arg(short = 'i', long = "show-info", help = "显示每个文件的编码猜测结果和置信度")]
@@ -22,9 +62,47 @@ pub struct Config {
     #[arg(short = 's', long = "scan-only", help = "只扫描文件编码，不执行转换操作")]
     pub scan_only: bool,
 
-    #[arg(short = 'b', long = "backup", help = "转换前将原文件备份为 .bak 文件")]
+    #[arg(
+        long = "dry-run",
+        global = true,
+        help = "试运行：解码并报告将要转换的文件，但不修改任何文件；用于 restore/clean-backups 时只列出将要处理的备份"
+    )]
+    pub dry_run: bool,
+
+    #[arg(
+        long = "diff",
+        help = "配合 --dry-run 输出原文件（UTF-8 有损显示）与转换结果的统一差异，带行号"
+    )]
+    pub diff: bool,
+
+    #[arg(
+        short = 'c',
+        long = "check",
+        help = "检查模式：不写入任何文件，发现非目标编码（默认 UTF-8）的文件时以 path:encoding 格式列出并以退出码 3 结束"
+    )]
+    pub check: bool,
+
+    #[arg(
+        short = 'b',
+        long = "backup",
+        help = "转换前将原文件备份为 .bak 文件，并记录到 --dir 下的 .gbk2utf8-manifest.jsonl 清单中"
+    )]
     pub backup: bool,
 
+    #[arg(
+        long = "backup-dir",
+        value_name = "DIR",
+        help = "将备份集中放到该目录（如 .gbk2utf8-backup）下的 <时间戳>/ 子目录中，保持相对 --dir 的路径结构，而不是在原文件旁创建 .bak；隐含 --backup，相对路径基于 --dir，遍历时自动跳过"
+    )]
+    pub backup_dir: Option<String>,
+
+    #[arg(
+        short = 'p',
+        long = "preserve-metadata",
+        help = "转换后保留原文件的修改时间、访问时间和扩展属性（权限与属主始终保留）"
+    )]
+    pub preserve_metadata: bool,
+
     #[arg(
         short = 'e',
         long = "extensions",
@@ -38,10 +116,50 @@ pub struct Config {
         short = 'm',
         long = "min-confidence",
         default_value_t = 0.8,
-        help = "判断为 GBK 的最小置信度"
+        help = "判断为源编码的最小置信度"
     )]
     pub min_confidence: f64,
 
+    #[arg(
+        short = 'f',
+        long = "from",
+        value_delimiter = ',',
+        default_value = "gbk,gb18030",
+        help = "允许转换的源编码（多个用英文逗号分隔，如 gbk,gb18030,big5,shift_jis）"
+    )]
+    pub from: Vec<String>,
+
+    #[arg(
+        long = "to",
+        default_value = "utf-8",
+        help = "转换的目标编码（如 utf-8、gbk、gb18030），默认 utf-8"
+    )]
+    pub to: String,
+
+    #[arg(
+        long = "eol",
+        value_enum,
+        default_value = "keep",
+        help = "转换时的换行符：keep 保持原样，lf 或 crlf 统一改写；已是目标编码、只有换行符不符的文件也会改写"
+    )]
+    pub eol: EolOption,
+
+    #[arg(
+        long = "bom",
+        value_enum,
+        default_value = "keep",
+        help = "转换为 UTF-8 时的 BOM：keep 保持源文件是否有 BOM，add 总是添加，strip 总是去掉；已是 UTF-8、只有 BOM 不符的文件也会改写"
+    )]
+    pub bom: BomOption,
+
+    #[arg(
+        long = "mixed",
+        value_enum,
+        default_value = "off",
+        help = "逐行识别 UTF-8 行与 GBK 等 --from 编码的行混用的文件：off 关闭，report 报告各编码的行范围并跳过，fix 按行范围分别解码后转换；开启后每个文件会整个读入内存"
+    )]
+    pub mixed: MixedOption,
+
     #[arg(
         long = "t",
         visible_alias = "tld",
@@ -50,6 +168,22 @@ pub struct Config {
     )]
     pub tld: Option<String>,
 
+    #[arg(
+        long = "candidates",
+        value_name = "N",
+        default_value_t = 0,
+        help = "列出每个文件最可能的 N 个候选编码，附带得分、解码错误数和解码后的示例行，便于排查误判"
+    )]
+    pub candidates: usize,
+
+    #[arg(
+        short = 'j',
+        long = "jobs",
+        default_value_t = 1,
+        help = "并行处理的线程数，0 表示使用全部 CPU 核心"
+    )]
+    pub jobs: usize,
+
     #[arg(
         long = "ignore-file",
         default_value = ".gbk2utf8ignore",
@@ -57,22 +191,101 @@ pub struct Config {
     )]
     pub ignore_file: String,
 
+    #[arg(
+        long = "config",
+        default_value = ".gbk2utf8.toml",
+        help = "项目配置文件路径（TOML），可保存所有命令行参数的默认值，并用 [[overrides]] 按路径强制指定源编码或跳过；相对路径基于 --dir，默认文件不存在时读取 Cargo.toml 的 [package.metadata.gbk2utf8]"
+    )]
+    pub config_file: String,
+
+    #[arg(long = "print-config", help = "打印合并命令行参数与项目配置文件后的实际设置（TOML 格式）并退出")]
+    pub print_config: bool,
+
+    #[arg(
+        short = 'g',
+        long = "gitignore",
+        help = "同时遵循 .gitignore、.ignore、全局 git 排除规则及各级目录中的忽略文件，并跳过 .git 目录"
+    )]
+    pub gitignore: bool,
+
+    #[arg(
+        long = "format",
+        value_enum,
+        default_value = "text",
+        help = "输出格式：text/json/ndjson（json 和 ndjson 便于 CI 解析）"
+    )]
+    pub format: OutputFormat,
+
     #[arg(
         long = "lang",
+        global = true,
         value_enum,
         default_value = "auto",
         help = "输出语言：auto/zh/en（默认 auto）"
     )]
     pub lang: LangOption,
+
+    /// 从项目配置文件加载的路径覆盖规则
+    #[arg(skip)]
+    pub overrides: PathOverrides,
+
+    /// 实际加载的项目配置文件
+    #[arg(skip)]
+    pub project_config: Option<PathBuf>,
+}
+
+/// 子命令；不指定时扫描并转换文件
+#[derive(Subcommand, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    /// 根据备份清单用备份文件恢复转换前的原文件
+    Restore,
+    /// 根据备份清单删除备份文件
+    CleanBackups,
+}
+
+/// `--eol` 换行符策略
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EolOption {
+    Keep,
+    Lf,
+    Crlf,
+}
+
+/// `--bom` BOM 策略
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BomOption {
+    Keep,
+    Add,
+    Strip,
+}
+
+/// `--mixed` 混合编码文件的处理方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MixedOption {
+    Off,
+    Report,
+    Fix,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum LangOption {
     Auto,
     Zh,
     En,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    Text,
+    Json,
+    Ndjson,
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum UiLang {
     Zh,
@@ -87,6 +300,51 @@ impl Config {
             LangOption::Auto => detect_ui_lang(),
         }
     }
+
+    /// 路径参数只有 `-` 时从标准输入读取、向标准输出写入
+    pub fn is_stdin(&self) -> bool {
+        matches!(self.paths.as_slice(), [path] if path.as_os_str() == "-")
+    }
+
+    /// 是否为文本输出；JSON/NDJSON 模式下标准输出只能包含报告本身
+    pub fn is_text_output(&self) -> bool {
+        self.format == OutputFormat::Text
+    }
+
+    /// 实际使用的工作线程数（`--jobs 0` 表示按 CPU 核心数）
+    pub fn worker_count(&self) -> usize {
+        match self.jobs {
+            0 => thread::available_parallelism().map_or(1, |n| n.get()),
+            n => n,
+        }
+    }
+
+    /// 解析 `--from` 中的编码标签，得到允许转换的源编码列表
+    pub fn source_encodings(&self) -> io::Result<Vec<&'static Encoding>> {
+        self.from.iter().map(|label| parse_encoding(label)).collect()
+    }
+
+    /// 解析 `--to` 指定的目标编码
+    pub fn target_encoding(&self) -> io::Result<&'static Encoding> {
+        let encoding = parse_encoding(&self.to)?;
+        if encoding.output_encoding() != encoding {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("unsupported target encoding: {}", encoding.name()),
+            ));
+        }
+        Ok(encoding)
+    }
+}
+
+/// 按 WHATWG 编码标签解析编码名（如 gbk、gb2312、big5、sjis）
+pub fn parse_encoding(label: &str) -> io::Result<&'static Encoding> {
+    Encoding::for_label(label.trim().as_bytes()).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("unknown encoding: {}", label),
+        )
+    })
 }
 
 fn detect_ui_lang() -> UiLang {
@@ -105,7 +363,11 @@ fn detect_ui_lang() -> UiLang {
 }
 
 fn tr(config: &Config, zh: &'static str, en: &'static str) -> &'static str {
-    match config.ui_lang() {
+    tr_lang(config.ui_lang(), zh, en)
+}
+
+fn tr_lang(lang: UiLang, zh: &'static str, en: &'static str) -> &'static str {
+    match lang {
         UiLang::Zh => zh,
         UiLang::En => en,
     }
@@ -115,149 +377,682 @@ fn tr(config: &Config, zh: &'static str, en: &'static str) -> &'static str {
 pub enum FileProcessOutcome {
     Converted,
     NoConversion,
+    CheckFailed,
 }
 
-#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
 pub struct ProcessingStats {
     pub converted: usize,
     pub failed: usize,
     pub no_conversion: usize,
+    pub check_failed: usize,
 }
 
 #[derive(Debug, Default)]
 pub struct RunResult {
     pub errors: HashMap<PathBuf, io::Error>,
     pub stats: ProcessingStats,
+    pub reports: Vec<FileReport>,
 }
 
-/// 扫描 GBK 文件并返回编码和置信度
+/// 扫描文件并返回编码和置信度；与 `inspect_file` 一样先应用配置文件的路径覆盖规则并跳过二进制文件
 pub fn scan_gbk_file(file_path: &Path, config: &Config) -> io::Result<Option<(String, f64)>> {
+    let sources = config.source_encodings()?;
+    let target = config.target_encoding()?;
+    let forced = match config.overrides.lookup(file_path) {
+        Some(PathOverride::Skip) => return Ok(None),
+        Some(PathOverride::Encoding(encoding)) => Some(encoding),
+        None => None,
+    };
     let mut file = fs::File::open(file_path)?;
-    let mut content = Vec::new();
-    file.read_to_end(&mut content)?;
-
-    if std::str::from_utf8(&content).is_ok() {
-        return Ok(Some(("utf-8".to_string(), 1.0)));
+    if forced.is_none() {
+        if binary::sniff_reader(&mut file)? {
+            return Ok(None);
+        }
+        file.rewind()?;
+        if let Some(kind) = utf32::sniff_reader(&mut file)? {
+            return Ok(Some((kind.name().to_lowercase(), 1.0)));
+        }
+        file.rewind()?;
     }
-
-    let mut detector = EncodingDetector::new();
-    detector.feed(&content, true);
-    let tld_bytes = config.tld.as_deref().map(str::as_bytes);
-    let (encoding, confident) = detector.guess_assess(tld_bytes, false);
+    let detected = apply_override(forced, detect::detect_file(&mut file, config)?, target);
+    let (encoding, confidence) = (detected.encoding, detected.confidence);
     let name = encoding.name().to_lowercase();
 
-    let confidence = if confident { 1.0 } else { 0.5 };
-
-    if name == "gbk" && confidence >= config.min_confidence {
-        Ok(Some((name, confidence)))
-    } else if config.show_info {
+    if is_unicode(encoding)
+        || encoding == target
+        || forced.is_some()
+        || (sources.contains(&encoding) && confidence >= config.min_confidence)
+        || config.show_info
+        || config.check
+    {
         Ok(Some((name, confidence)))
     } else {
         Ok(None)
     }
 }
 
-/// 将 GBK 文件转换为 UTF-8
+/// UTF-8 和 UTF-16（BOM 或空字节分布）可以无歧义地识别，不受 `--from` 限制
+fn is_unicode(encoding: &'static Encoding) -> bool {
+    encoding == UTF_8 || encoding == UTF_16LE || encoding == UTF_16BE
+}
+
+/// 判断检测到的编码是否需要转换为目标编码
+fn needs_conversion(
+    encoding: &'static Encoding,
+    target: &'static Encoding,
+    sources: &[&'static Encoding],
+) -> bool {
+    encoding != target && (sources.contains(&encoding) || is_unicode(encoding))
+}
+
+/// 内容是否以 `encoding` 的 BOM 开头
+fn has_bom<R: Read>(reader: R, encoding: &'static Encoding) -> io::Result<bool> {
+    let mut head = Vec::with_capacity(3);
+    reader.take(3).read_to_end(&mut head)?;
+    Ok(Encoding::for_bom(&head).is_some_and(|(bom, _)| bom == encoding))
+}
+
+/// 从头读取已打开的源文件用于解码：UTF-32 文件先解码为 UTF-8 字节流，之后按 UTF-8 处理
+fn open_source(mut file: &fs::File, utf32: Option<Utf32>) -> io::Result<Box<dyn Read + '_>> {
+    file.rewind()?;
+    Ok(match utf32 {
+        Some(kind) => Box::new(Utf32Reader::new(file, kind)),
+        None => Box::new(file),
+    })
+}
+
+/// 将 GBK 文件转换为目标编码（默认 UTF-8）
 pub fn convert_gbk_file(file_path: &Path, config: &Config) -> io::Result<Option<PathBuf>> {
-    let mut file = fs::File::open(file_path)?;
+    convert_file(file_path, GBK, config)
+}
+
+/// 读取文件并按指定编码严格解码，返回原始字节（UTF-32 文件为解码后的 UTF-8 字节）和解码后的文本
+fn decode_file(
+    file: &fs::File,
+    encoding: &'static Encoding,
+    utf32: Option<Utf32>,
+) -> io::Result<(Vec<u8>, String)> {
     let mut content = Vec::new();
-    file.read_to_end(&mut content)?;
-
-    match GBK.decode(&content, DecoderTrap::Strict) {
-        Ok(decoded) => {
-            let mut backup_path = None;
-            if config.backup {
-                let bak = file_path.with_extension(format!(
-                    "{}.bak",
-                    file_path.extension().unwrap_or_default().to_string_lossy()
-                ));
-                fs::copy(file_path, &bak)?;
-                backup_path = Some(bak);
-            }
+    open_source(file, utf32)?.read_to_end(&mut content)?;
+    let decoded = decode_bytes(&content, encoding)?;
+    Ok((content, decoded))
+}
+
+/// 按指定编码严格解码字节内容，出现非法序列时返回错误
+fn decode_bytes(content: &[u8], encoding: &'static Encoding) -> io::Result<String> {
+    match encoding.decode_without_bom_handling_and_without_replacement(content) {
+        Some(decoded) => Ok(decoded.into_owned()),
+        None => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} decode failed", encoding.name()),
+        )),
+    }
+}
 
-            let mut file = fs::File::create(file_path)?;
-            file.write_all(decoded.as_bytes())?;
-            Ok(backup_path)
+/// 从 `input` 读取字节，用与 `scan_gbk_file` 相同的逻辑识别编码，并将转换结果写入 `output`；
+/// 无需转换或无法确定编码时原样输出，`--check` 时只检查、不输出。编码只根据前 `STREAM_DETECTION_LIMIT` 字节识别，
+/// 之后按块流式转换，内存占用与输入大小无关。
+pub fn convert_stream<R: Read, W: Write>(
+    input: &mut R,
+    output: &mut W,
+    config: &Config,
+) -> io::Result<FileReport> {
+    let sources = config.source_encodings()?;
+    let target = config.target_encoding()?;
+    let (encoding, confidence, prefix) =
+        detect::detect_prefix(input, config, STREAM_DETECTION_LIMIT)?;
+    let mut content = prefix.as_slice().chain(input);
+
+    let mut report = FileReport::new(Path::new("-"));
+    if binary::looks_binary(&prefix) {
+        report.action = FileAction::Binary;
+        if !config.check {
+            io::copy(&mut content, output)?;
+            output.flush()?;
+        }
+        return Ok(report);
+    }
+    report.encoding = Some(encoding.name().to_lowercase());
+    report.confidence = Some(confidence);
+
+    let utf32 = utf32::sniff(&prefix, prefix.len() < STREAM_DETECTION_LIMIT);
+    if config.check {
+        report.action = if utf32.is_none() && encoding == target {
+            FileAction::Unchanged
+        } else {
+            FileAction::CheckFailed
+        };
+        if let Some(kind) = utf32 {
+            report.encoding = Some(kind.name().to_lowercase());
+            report.confidence = Some(1.0);
         }
-        Err(_) => Err(io::Error::new(io::ErrorKind::InvalidData, "GBK decode failed")),
+        return Ok(report);
     }
+
+    if let Some(kind) = utf32 {
+        report.encoding = Some(kind.name().to_lowercase());
+        report.confidence = Some(1.0);
+        let mut decoded = Utf32Reader::new(content, kind);
+        transcode(&mut decoded, output, UTF_8, target, config.eol, config.bom)?;
+        report.action = FileAction::Converted;
+        report.target = Some(target.name().to_lowercase());
+        output.flush()?;
+        return Ok(report);
+    }
+
+    if encoding == target && config.eol == EolOption::Keep && config.bom == BomOption::Keep {
+        report.action = FileAction::Unchanged;
+        io::copy(&mut content, output)?;
+    } else if encoding == target {
+        // 无法回读标准输入，只能边改写换行符和 BOM 边输出，根据是否有改写决定报告结果
+        let changed = transcode(&mut content, output, encoding, target, config.eol, config.bom)?;
+        report.action = if changed > 0 {
+            FileAction::Converted
+        } else {
+            FileAction::Unchanged
+        };
+        report.target = Some(target.name().to_lowercase());
+    } else if needs_conversion(encoding, target, &sources) && confidence >= config.min_confidence {
+        transcode(&mut content, output, encoding, target, config.eol, config.bom)?;
+        report.action = FileAction::Converted;
+        report.target = Some(target.name().to_lowercase());
+    } else {
+        report.action = if confidence < config.min_confidence {
+            FileAction::Uncertain
+        } else {
+            FileAction::Skipped
+        };
+        io::copy(&mut content, output)?;
+    }
+
+    output.flush()?;
+    Ok(report)
 }
 
-/// 处理单个文件，根据配置进行扫描或转换
-pub fn handle_file(file_path: &Path, config: &Config) -> io::Result<FileProcessOutcome> {
-    match scan_gbk_file(file_path, config)? {
-        Some((encoding_name, confidence)) => {
-            let show_detail = |prefix: &str, msg: &str| {
-                if config.show_info {
-                    println!(
-                        "{} {}: {} = {}, {} = {:.2}{}",
-                        prefix,
-                        file_path.display(),
-                        tr(config, "编码", "encoding"),
-                        encoding_name,
-                        tr(config, "置信度", "confidence"),
-                        confidence,
-                        msg
-                    );
-                } else {
-                    println!(
-                        "{} {}: {} = {}{}",
-                        prefix,
-                        file_path.display(),
-                        tr(config, "编码", "encoding"),
-                        encoding_name,
-                        msg
-                    );
-                }
-            };
+/// 标准输入到标准输出的转换模式（路径参数为 `-`），提示信息写入标准错误；
+/// `--check` 时不输出内容，输入不是目标编码时返回 `CheckFailed`
+pub fn convert_stdin(config: &Config) -> io::Result<FileProcessOutcome> {
+    let report = convert_stream(&mut io::stdin().lock(), &mut io::stdout().lock(), config)?;
+    if config.show_info
+        || matches!(
+            report.action,
+            FileAction::Skipped
+                | FileAction::Uncertain
+                | FileAction::Binary
+                | FileAction::CheckFailed
+        )
+    {
+        eprint!("{}", report::render_text(&report, config));
+    }
+    Ok(match report.action {
+        FileAction::CheckFailed => FileProcessOutcome::CheckFailed,
+        FileAction::Converted => FileProcessOutcome::Converted,
+        _ => FileProcessOutcome::NoConversion,
+    })
+}
 
-            match encoding_name.as_str() {
-                "utf-8" => {
-                    show_detail("✅", "");
-                    return Ok(FileProcessOutcome::NoConversion);
-                }
-                "gbk" => {
-                    if config.scan_only {
-                        show_detail(
-                            "⏩",
-                            tr(config, "，未转换（扫描模式）", " (not converted, scan-only mode)"),
-                        );
-                        return Ok(FileProcessOutcome::NoConversion);
-                    } else {
-                        match convert_gbk_file(file_path, config) {
-                            Ok(Some(bak)) if config.show_info => {
-                                println!(
-                                    "📦 {}: {}",
-                                    tr(config, "备份创建", "backup created"),
-                                    bak.display()
-                                );
-                            }
-                            Ok(_) => {}
-                            Err(e) => return Err(e),
-                        }
-                        show_detail("🔄", tr(config, "，已转换为 UTF-8", " (converted to UTF-8)"));
-                        return Ok(FileProcessOutcome::Converted);
-                    }
-                }
-                _ => {
-                    show_detail("❌", tr(config, "，跳过", " (skipped)"));
-                    return Ok(FileProcessOutcome::NoConversion);
+/// 将指定源编码的文件转换为目标编码（默认 UTF-8）并按 `--eol`、`--bom` 改写换行符和 BOM，
+/// 按块流式转换，内存占用与文件大小无关
+pub fn convert_file(
+    file_path: &Path,
+    encoding: &'static Encoding,
+    config: &Config,
+) -> io::Result<Option<PathBuf>> {
+    convert_source(file_path, &fs::File::open(file_path)?, encoding, None, config)
+}
+
+/// 同 `convert_file`，`utf32` 不为空时先将 UTF-32 文件解码为 UTF-8 字节流
+fn convert_source(
+    file_path: &Path,
+    file: &fs::File,
+    encoding: &'static Encoding,
+    utf32: Option<Utf32>,
+    config: &Config,
+) -> io::Result<Option<PathBuf>> {
+    let target = config.target_encoding()?;
+    let mut input = open_source(file, utf32)?;
+    let mut atomic = AtomicWriter::new(file_path)?;
+    {
+        let mut output = io::BufWriter::new(atomic.file());
+        transcode(&mut input, &mut output, encoding, target, config.eol, config.bom)?;
+        output.flush()?;
+    }
+    commit_with_backup(file_path, atomic, config)
+}
+
+/// 按 `--backup`、`--backup-dir` 备份原文件后提交原子写入，返回备份路径
+fn commit_with_backup(
+    file_path: &Path,
+    atomic: AtomicWriter,
+    config: &Config,
+) -> io::Result<Option<PathBuf>> {
+    let mut backup_path = None;
+    if config.backup || config.backup_dir.is_some() {
+        backup_path = Some(backup::create_backup(file_path, config)?);
+    }
+
+    atomic.commit(config.preserve_metadata)?;
+    Ok(backup_path)
+}
+
+/// 原子写入：先写入同目录下的临时文件并 fsync，再重命名覆盖原文件，
+/// 避免写入中途崩溃或磁盘写满时留下被截断的源文件。未提交时临时文件会被自动删除。
+/// 符号链接先解析为实际文件，写入链接指向的文件而不是用普通文件替换链接本身
+pub(crate) struct AtomicWriter {
+    path: PathBuf,
+    metadata: fs::Metadata,
+    temp: tempfile::NamedTempFile,
+}
+
+impl AtomicWriter {
+    pub(crate) fn new(file_path: &Path) -> io::Result<Self> {
+        let path = fs::canonicalize(file_path)?;
+        let metadata = fs::metadata(&path)?;
+        let dir = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        let temp = tempfile::Builder::new()
+            .prefix(".gbk2utf8-")
+            .suffix(".tmp")
+            .tempfile_in(dir)?;
+        Ok(Self {
+            path,
+            metadata,
+            temp,
+        })
+    }
+
+    pub(crate) fn file(&mut self) -> &mut fs::File {
+        self.temp.as_file_mut()
+    }
+
+    /// 复制权限与属主后重命名覆盖原文件；
+    /// `preserve_metadata` 为真时额外保留原文件的访问/修改时间和扩展属性
+    pub(crate) fn commit(self, preserve_metadata: bool) -> io::Result<()> {
+        if is_hard_linked(&self.metadata) {
+            return self.write_through(preserve_metadata);
+        }
+        let file = self.temp.as_file();
+        file.set_permissions(self.metadata.permissions())?;
+        copy_ownership(file, &self.metadata);
+        if preserve_metadata {
+            copy_xattrs(&self.path, file);
+            file.set_times(self.original_times()?)?;
+        }
+        file.sync_all()?;
+        self.temp.persist(&self.path).map_err(|e| e.error)?;
+        Ok(())
+    }
+
+    /// 有多个硬链接的文件重命名覆盖后会与其他链接断开，改为把临时文件的内容写回原文件；
+    /// 这种情况下不是原子写入，但临时文件已完整写入并 fsync，写回失败时仍可从中恢复
+    fn write_through(mut self, preserve_metadata: bool) -> io::Result<()> {
+        let times = self.original_times()?;
+        let temp = self.temp.as_file_mut();
+        temp.sync_all()?;
+        temp.rewind()?;
+        let mut file = fs::OpenOptions::new()
+            .write(true)
+            .truncate(true)
+            .open(&self.path)?;
+        io::copy(temp, &mut file)?;
+        if preserve_metadata {
+            file.set_times(times)?;
+        }
+        file.sync_all()
+    }
+
+    fn original_times(&self) -> io::Result<fs::FileTimes> {
+        let mut times = fs::FileTimes::new().set_modified(self.metadata.modified()?);
+        if let Ok(accessed) = self.metadata.accessed() {
+            times = times.set_accessed(accessed);
+        }
+        Ok(times)
+    }
+}
+
+/// 文件是否有多个硬链接
+#[cfg(unix)]
+fn is_hard_linked(metadata: &fs::Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    metadata.nlink() > 1
+}
+
+#[cfg(not(unix))]
+fn is_hard_linked(_metadata: &fs::Metadata) -> bool {
+    false
+}
+
+/// 尽量保留原文件的属主和属组（非 root 用户通常无权修改，失败时忽略）
+#[cfg(unix)]
+fn copy_ownership(file: &fs::File, metadata: &fs::Metadata) {
+    use std::os::unix::fs::MetadataExt;
+    let _ = std::os::unix::fs::fchown(file, Some(metadata.uid()), Some(metadata.gid()));
+}
+
+#[cfg(not(unix))]
+fn copy_ownership(_file: &fs::File, _metadata: &fs::Metadata) {}
+
+/// 尽量复制原文件的扩展属性（文件系统不支持时忽略）
+#[cfg(unix)]
+fn copy_xattrs(source: &Path, file: &fs::File) {
+    use xattr::FileExt;
+    let Ok(names) = xattr::list(source) else {
+        return;
+    };
+    for name in names {
+        if let Ok(Some(value)) = xattr::get(source, &name) {
+            let _ = file.set_xattr(&name, &value);
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn copy_xattrs(_source: &Path, _file: &fs::File) {}
+
+/// 生成原文件（按 UTF-8 有损显示）与转换结果之间的统一差异，每行带新旧行号
+pub fn render_diff(file_path: &Path, original: &[u8], converted: &str) -> String {
+    let original = String::from_utf8_lossy(original);
+    let diff = TextDiff::from_lines(original.as_ref(), converted);
+    let mut out = String::new();
+    let _ = writeln!(out, "--- {}\t(original)", file_path.display());
+    let _ = writeln!(out, "+++ {}\t(converted)", file_path.display());
+
+    for group in diff.grouped_ops(3) {
+        let (first, last) = (&group[0], &group[group.len() - 1]);
+        let old_range = first.old_range().start..last.old_range().end;
+        let new_range = first.new_range().start..last.new_range().end;
+        let _ = writeln!(
+            out,
+            "@@ -{},{} +{},{} @@",
+            old_range.start + 1,
+            old_range.len(),
+            new_range.start + 1,
+            new_range.len()
+        );
+
+        for op in &group {
+            for change in diff.iter_changes(op) {
+                let sign = match change.tag() {
+                    ChangeTag::Delete => '-',
+                    ChangeTag::Insert => '+',
+                    ChangeTag::Equal => ' ',
+                };
+                let line_no = |index: Option<usize>| {
+                    index.map_or_else(String::new, |i| (i + 1).to_string())
+                };
+                let _ = write!(
+                    out,
+                    "{}{:>5} {:>5} | {}",
+                    sign,
+                    line_no(change.old_index()),
+                    line_no(change.new_index()),
+                    change.value()
+                );
+                if change.missing_newline() {
+                    out.push('\n');
                 }
             }
         }
+    }
+
+    out
+}
+
+/// 处理单个文件，根据配置进行扫描或转换，并按 `--format` 输出结果
+pub fn handle_file(file_path: &Path, config: &Config) -> io::Result<FileProcessOutcome> {
+    let (report, result) = inspect_file(file_path, config);
+    match config.format {
+        OutputFormat::Text => print!("{}", report::render_text(&report, config)),
+        OutputFormat::Json | OutputFormat::Ndjson => {
+            println!("{}", report::render_ndjson_file(&report))
+        }
+    }
+    result
+}
+
+/// 处理单个文件并返回结构化的处理结果，不产生任何输出
+pub fn inspect_file(
+    file_path: &Path,
+    config: &Config,
+) -> (FileReport, io::Result<FileProcessOutcome>) {
+    let mut report = FileReport::new(file_path);
+    let result = inspect_file_into(file_path, config, &mut report);
+    if let Err(e) = &result {
+        report.action = FileAction::Failed;
+        report.error = Some(e.to_string());
+    }
+    (report, result)
+}
+
+fn inspect_file_into(
+    file_path: &Path,
+    config: &Config,
+    report: &mut FileReport,
+) -> io::Result<FileProcessOutcome> {
+    let forced = match config.overrides.lookup(file_path) {
+        Some(PathOverride::Skip) => {
+            report.action = FileAction::Excluded;
+            return Ok(FileProcessOutcome::NoConversion);
+        }
+        Some(PathOverride::Encoding(encoding)) => Some(encoding),
+        None => None,
+    };
+
+    // 各步骤共用同一个文件句柄，每次从头读取前先回到文件开头
+    let mut file = fs::File::open(file_path)?;
+
+    // 二进制内容可能被误判为 GBK，在识别编码之前跳过；配置文件强制指定编码时以配置为准
+    if forced.is_none() && binary::sniff_reader(&mut file)? {
+        report.action = FileAction::Binary;
+        return Ok(FileProcessOutcome::NoConversion);
+    }
+
+    if config.candidates > 0 {
+        file.rewind()?;
+        report.candidates = rank_candidates(&mut file, config, config.candidates)?;
+    }
+
+    // UTF-32 不在 encoding_rs 支持范围内，解码为 UTF-8 字节流后按 UTF-8 处理
+    let utf32 = match forced {
+        Some(_) => None,
         None => {
-            println!(
-                "⚠️ {}: {}",
-                file_path.display(),
-                tr(
-                    config,
-                    "编码不确定或置信度不足，跳过",
-                    "uncertain encoding or low confidence, skipped"
-                )
+            file.rewind()?;
+            utf32::sniff_reader(&mut file)?
+        }
+    };
+    // UTF-8 行与 GBK 等行混用的文件整体解码必然失败，按行识别后单独处理
+    if config.mixed != MixedOption::Off && forced.is_none() && utf32.is_none() {
+        let mut content = Vec::new();
+        file.rewind()?;
+        file.read_to_end(&mut content)?;
+        if let Some(lines) = mixed::analyze(&content, &config.source_encodings()?) {
+            return inspect_mixed(file_path, config, report, &content, &lines);
+        }
+    }
+    // 识别编码时读完了整个文件的，顺带得到换行符统计和 BOM
+    let (encoding, confidence, scanned) = match utf32 {
+        Some(_) => (UTF_8, 1.0, None),
+        None => {
+            file.rewind()?;
+            let detected = apply_override(
+                forced,
+                detect::detect_file(&mut file, config)?,
+                config.target_encoding()?,
             );
-            return Ok(FileProcessOutcome::NoConversion);
+            let scanned = detected
+                .line_endings
+                .map(|line_endings| (line_endings, detected.bom));
+            (detected.encoding, detected.confidence, scanned)
+        }
+    };
+    // 报告中总是给出识别结果，是否显示由 -i 决定，不影响报告内容
+    report.encoding = Some(match utf32 {
+        Some(kind) => kind.name().to_lowercase(),
+        None => encoding.name().to_lowercase(),
+    });
+    report.confidence = Some(confidence);
+
+    let sources = config.source_encodings()?;
+    let target = config.target_encoding()?;
+    let is_target = utf32.is_none() && encoding == target;
+
+    if config.check {
+        return if is_target {
+            report.action = FileAction::Unchanged;
+            Ok(FileProcessOutcome::NoConversion)
+        } else {
+            report.action = FileAction::CheckFailed;
+            Ok(FileProcessOutcome::CheckFailed)
+        };
+    }
+
+    match scanned {
+        Some((line_endings, bom)) => {
+            report.line_endings = line_endings;
+            report.bom = bom;
+        }
+        None => {
+            // 换行符统计只用于 --eol 改写和提示换行符混用，大文件没读完时只在需要时再完整扫描一遍
+            if config.eol != EolOption::Keep
+                || config.scan_only
+                || config.show_info
+                || !config.is_text_output()
+            {
+                report.line_endings = scan_line_endings(&mut open_source(&file, utf32)?, encoding)?;
+            }
+            report.bom = has_bom(open_source(&file, utf32)?, encoding)?;
+        }
+    }
+    // 已是目标编码的文件只需要改写换行符或 BOM
+    let fix_layout = report
+        .line_endings
+        .is_some_and(|line_endings| line_endings.needs_fix(config.eol))
+        || transcode::writes_bom(target, config.bom, report.bom) != report.bom;
+
+    if is_target && !fix_layout {
+        report.action = FileAction::Unchanged;
+        return Ok(FileProcessOutcome::NoConversion);
+    }
+    let convert = is_target
+        || utf32.is_some()
+        || forced.is_some()
+        || (needs_conversion(encoding, target, &sources) && confidence >= config.min_confidence);
+    if !convert {
+        report.action = if confidence < config.min_confidence {
+            FileAction::Uncertain
+        } else {
+            FileAction::Skipped
+        };
+        return Ok(FileProcessOutcome::NoConversion);
+    }
+
+    report.target = Some(target.name().to_lowercase());
+
+    if config.scan_only {
+        report.action = FileAction::ScanOnly;
+        return Ok(FileProcessOutcome::NoConversion);
+    }
+
+    if config.dry_run {
+        if config.diff {
+            let (original, decoded) = decode_file(&file, encoding, utf32)?;
+            let body = decoded.strip_prefix('\u{FEFF}').unwrap_or(&decoded);
+            let mut converted = eol::normalize(body, config.eol);
+            if transcode::writes_bom(target, config.bom, report.bom) {
+                converted.insert(0, '\u{FEFF}');
+            }
+            encode_text(&converted, target)?;
+            report.diff = Some(render_diff(file_path, &original, &converted));
+        } else {
+            let mut input = open_source(&file, utf32)?;
+            transcode(
+                &mut input,
+                &mut io::sink(),
+                encoding,
+                target,
+                config.eol,
+                config.bom,
+            )?;
+        }
+        report.action = FileAction::WouldConvert;
+        return Ok(FileProcessOutcome::NoConversion);
+    }
+
+    report.backup = convert_source(file_path, &file, encoding, utf32, config)?;
+    report.action = FileAction::Converted;
+    Ok(FileProcessOutcome::Converted)
+}
+
+/// 配置文件强制指定的编码优先于识别结果，不受 `--from` 和 `--min-confidence` 限制；
+/// 但已是目标编码的文件（如上次运行已转换过）以识别结果为准，避免再按强制编码解码一次造成乱码。
+/// 改用强制编码时换行符和 BOM 需要按该编码重新扫描
+fn apply_override(
+    forced: Option<&'static Encoding>,
+    detected: detect::FileDetection,
+    target: &'static Encoding,
+) -> detect::FileDetection {
+    match forced {
+        Some(encoding) if detected.encoding != target => detect::FileDetection {
+            encoding,
+            confidence: 1.0,
+            bom: false,
+            line_endings: None,
+        },
+        _ => detected,
+    }
+}
+
+/// 处理 `--mixed` 识别出的混合编码文件：报告各编码的行范围，`--mixed fix` 时逐段解码后转换为目标编码
+fn inspect_mixed(
+    file_path: &Path,
+    config: &Config,
+    report: &mut FileReport,
+    content: &[u8],
+    lines: &mixed::MixedLines,
+) -> io::Result<FileProcessOutcome> {
+    let target = config.target_encoding()?;
+    report.encoding = Some(mixed::MIXED.to_string());
+    report.confidence = Some(1.0);
+    report.segments = lines.segments();
+    report.bom = lines.bom;
+    report.line_endings = scan_line_endings(&mut &content[..], UTF_8)?;
+
+    if config.check {
+        report.action = FileAction::CheckFailed;
+        return Ok(FileProcessOutcome::CheckFailed);
+    }
+    if config.mixed == MixedOption::Report {
+        report.action = FileAction::Mixed;
+        return Ok(FileProcessOutcome::NoConversion);
+    }
+
+    report.target = Some(target.name().to_lowercase());
+    if config.scan_only {
+        report.action = FileAction::ScanOnly;
+        return Ok(FileProcessOutcome::NoConversion);
+    }
+
+    let mut converted = eol::normalize(&lines.decode()?, config.eol);
+    if transcode::writes_bom(target, config.bom, lines.bom) {
+        converted.insert(0, '\u{FEFF}');
+    }
+    let encoded = encode_text(&converted, target)?;
+    if config.dry_run {
+        if config.diff {
+            report.diff = Some(render_diff(file_path, content, &converted));
         }
+        report.action = FileAction::WouldConvert;
+        return Ok(FileProcessOutcome::NoConversion);
     }
+
+    let mut atomic = AtomicWriter::new(file_path)?;
+    atomic.file().write_all(&encoded)?;
+    report.backup = commit_with_backup(file_path, atomic, config)?;
+    report.action = FileAction::Converted;
+    Ok(FileProcessOutcome::Converted)
 }
 
 pub fn build_ignore_matcher(root_dir: &Path, config: &Config) -> io::Result<Gitignore> {
@@ -268,11 +1063,13 @@ pub fn build_ignore_matcher(root_dir: &Path, config: &Config) -> io::Result<Giti
         if let Some(e) = builder.add(&absolute_ignore_file) {
             return Err(io::Error::new(io::ErrorKind::InvalidInput, e.to_string()));
         }
-        println!(
-            "🚫 {}: {}",
-            tr(config, "忽略规则文件", "ignore rules file"),
-            absolute_ignore_file.display()
-        );
+        if config.is_text_output() && !config.check {
+            println!(
+                "🚫 {}: {}",
+                tr(config, "忽略规则文件", "ignore rules file"),
+                absolute_ignore_file.display()
+            );
+        }
     }
 
     builder
@@ -281,11 +1078,16 @@ pub fn build_ignore_matcher(root_dir: &Path, config: &Config) -> io::Result<Giti
 }
 
 fn resolve_ignore_file_path(root_dir: &Path, config: &Config) -> PathBuf {
-    let ignore_file_path = Path::new(&config.ignore_file);
-    if ignore_file_path.is_absolute() {
-        ignore_file_path.to_path_buf()
+    resolve_path_in_dir(root_dir, &config.ignore_file)
+}
+
+/// 相对路径基于 `--dir` 解析，绝对路径保持不变
+pub(crate) fn resolve_path_in_dir(root_dir: &Path, path: &str) -> PathBuf {
+    let path = Path::new(path);
+    if path.is_absolute() {
+        path.to_path_buf()
     } else {
-        root_dir.join(ignore_file_path)
+        root_dir.join(path)
     }
 }
 
@@ -293,6 +1095,109 @@ pub fn should_ignore(path: &Path, is_dir: bool, ignore_matcher: &Gitignore) -> b
     ignore_matcher.matched(path, is_dir).is_ignore()
 }
 
+/// 展开命令行传入的路径：文件直接处理，目录递归遍历，glob 模式（如 `src/**/*.c`）匹配后处理，
+/// 均应用与目录遍历相同的扩展名和忽略规则；结果按传入顺序去重
+pub fn collect_paths(
+    root_dir: &Path,
+    paths: &[PathBuf],
+    config: &Config,
+    ignore_matcher: &Gitignore,
+) -> io::Result<Vec<PathBuf>> {
+    let ignore_file_path = resolve_ignore_file_path(root_dir, config);
+    let mut files = Vec::new();
+
+    for path in paths {
+        if path.is_dir() {
+            if config.gitignore {
+                collect_files_gitignore(root_dir, path, config, ignore_matcher, &mut files)?;
+            } else {
+                collect_files(root_dir, path, config, ignore_matcher, &mut files)?;
+            }
+        } else if !path.exists() && is_glob(path) {
+            collect_glob(root_dir, path, config, ignore_matcher, &mut files)?;
+        } else if *path != ignore_file_path
+            && has_wanted_extension(path, config)
+            && !is_ignored_path(root_dir, path, config, ignore_matcher)
+        {
+            files.push(path.clone());
+        }
+    }
+
+    let mut seen = HashSet::new();
+    files.retain(|path| seen.insert(path.clone()));
+    Ok(files)
+}
+
+fn is_glob(path: &Path) -> bool {
+    path.to_string_lossy().contains(['*', '?', '[', '{'])
+}
+
+/// 从 glob 中不含通配符的前缀目录开始遍历，再用剩余部分匹配相对路径
+fn collect_glob(
+    root_dir: &Path,
+    pattern: &Path,
+    config: &Config,
+    ignore_matcher: &Gitignore,
+    files: &mut Vec<PathBuf>,
+) -> io::Result<()> {
+    let mut base = PathBuf::new();
+    let mut rest = PathBuf::new();
+    for component in pattern.components() {
+        if rest.as_os_str().is_empty() && !is_glob(Path::new(&component)) {
+            base.push(component);
+        } else {
+            rest.push(component);
+        }
+    }
+    if base.as_os_str().is_empty() {
+        base = PathBuf::from(".");
+    }
+    if !base.is_dir() {
+        return Ok(());
+    }
+
+    let matcher = GlobBuilder::new(&rest.to_string_lossy())
+        .literal_separator(true)
+        .build()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))?
+        .compile_matcher();
+
+    let mut candidates = Vec::new();
+    if config.gitignore {
+        collect_files_gitignore(root_dir, &base, config, ignore_matcher, &mut candidates)?;
+    } else {
+        collect_files(root_dir, &base, config, ignore_matcher, &mut candidates)?;
+    }
+    files.extend(
+        candidates
+            .into_iter()
+            .filter(|path| matcher.is_match(path.strip_prefix(&base).unwrap_or(path))),
+    );
+    Ok(())
+}
+
+fn is_ignored_path(
+    root_dir: &Path,
+    path: &Path,
+    config: &Config,
+    ignore_matcher: &Gitignore,
+) -> bool {
+    let relative_path = path.strip_prefix(root_dir).unwrap_or(path);
+    // 根目录之外的绝对路径不受忽略规则约束
+    let ignored = !relative_path.has_root()
+        && ignore_matcher
+            .matched_path_or_any_parents(relative_path, false)
+            .is_ignore();
+    if ignored && config.show_info && config.is_text_output() {
+        println!(
+            "🚫 {}: {}",
+            path.display(),
+            tr(config, "命中忽略规则，跳过", "matched ignore rules, skipped")
+        );
+    }
+    ignored
+}
+
 /// 递归处理目录中的所有文件
 pub fn process_files_in_dir(
     root_dir: &Path,
@@ -301,19 +1206,52 @@ pub fn process_files_in_dir(
     ignore_matcher: &Gitignore,
     err: &mut HashMap<PathBuf, io::Error>,
     stats: &mut ProcessingStats,
+) -> io::Result<()> {
+    let mut files = Vec::new();
+    if config.gitignore {
+        collect_files_gitignore(root_dir, dir, config, ignore_matcher, &mut files)?;
+    } else {
+        collect_files(root_dir, dir, config, ignore_matcher, &mut files)?;
+    }
+
+    let mut result = RunResult {
+        errors: std::mem::take(err),
+        stats: *stats,
+        reports: Vec::new(),
+    };
+    process_files(&files, config, &mut result);
+    *err = result.errors;
+    *stats = result.stats;
+    Ok(())
+}
+
+/// 递归收集目录中需要处理的文件（已应用忽略规则和扩展名过滤），按路径排序以保证输出顺序稳定
+pub fn collect_files(
+    root_dir: &Path,
+    dir: &Path,
+    config: &Config,
+    ignore_matcher: &Gitignore,
+    files: &mut Vec<PathBuf>,
 ) -> io::Result<()> {
     let ignore_file_path = resolve_ignore_file_path(root_dir, config);
+    let backup_root = backup::canonical_backup_root(config);
+    let mut paths = fs::read_dir(dir)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<io::Result<Vec<_>>>()?;
+    paths.sort();
 
-    for entry in fs::read_dir(dir)? {
-        let path = entry?.path();
+    for path in paths {
         let relative_path = path.strip_prefix(root_dir).unwrap_or(&path);
 
         if path.is_file() && path == ignore_file_path {
             continue;
         }
+        if path.is_dir() && backup::is_backup_root(&path, backup_root.as_deref()) {
+            continue;
+        }
 
         if should_ignore(relative_path, path.is_dir(), ignore_matcher) {
-            if config.show_info {
+            if config.show_info && config.is_text_output() {
                 println!(
                     "🚫 {}: {}",
                     path.display(),
@@ -324,42 +1262,200 @@ pub fn process_files_in_dir(
         }
 
         if path.is_dir() {
-            process_files_in_dir(root_dir, &path, config, ignore_matcher, err, stats)?;
-        } else if path.is_file() {
-            let ext = path
-                .extension()
-                .unwrap_or_default()
-                .to_string_lossy()
-                .to_lowercase();
-            if config.extensions.iter().any(|e| e.to_lowercase() == ext) {
-                match handle_file(&path, config) {
-                    Ok(FileProcessOutcome::Converted) => stats.converted += 1,
-                    Ok(FileProcessOutcome::NoConversion) => stats.no_conversion += 1,
-                    Err(e) => {
-                        stats.failed += 1;
-                        err.insert(path.clone(), e);
-                    }
+            collect_files(root_dir, &path, config, ignore_matcher, files)?;
+        } else if path.is_file() && has_wanted_extension(&path, config) {
+            files.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+/// 与 ripgrep 相同的方式收集文件：遵循各级目录的 .gitignore / .ignore、
+/// .git/info/exclude 和全局 git 排除规则，同时仍应用 `--ignore-file` 规则
+pub fn collect_files_gitignore(
+    root_dir: &Path,
+    dir: &Path,
+    config: &Config,
+    ignore_matcher: &Gitignore,
+    files: &mut Vec<PathBuf>,
+) -> io::Result<()> {
+    let root = root_dir.to_path_buf();
+    let matcher = ignore_matcher.clone();
+    let ignore_file_path = resolve_ignore_file_path(root_dir, config);
+    let backup_root = backup::canonical_backup_root(config);
+    let show_info = config.show_info && config.is_text_output();
+    let lang = config.ui_lang();
+
+    let walker = WalkBuilder::new(dir)
+        .hidden(false)
+        .parents(true)
+        .ignore(true)
+        .git_ignore(true)
+        .git_global(true)
+        .git_exclude(true)
+        .require_git(false)
+        .sort_by_file_path(|a, b| a.cmp(b))
+        .filter_entry(move |entry| {
+            if entry.depth() == 0 {
+                return true;
+            }
+            let path = entry.path();
+            let is_dir = entry.file_type().is_some_and(|t| t.is_dir());
+            if is_dir && entry.file_name() == ".git" {
+                return false;
+            }
+            if !is_dir && path == ignore_file_path {
+                return false;
+            }
+            if is_dir && backup::is_backup_root(path, backup_root.as_deref()) {
+                return false;
+            }
+            let relative_path = path.strip_prefix(&root).unwrap_or(path);
+            if should_ignore(relative_path, is_dir, &matcher) {
+                if show_info {
+                    println!(
+                        "🚫 {}: {}",
+                        path.display(),
+                        tr_lang(lang, "命中忽略规则，跳过", "matched ignore rules, skipped")
+                    );
                 }
+                return false;
             }
+            true
+        })
+        .build();
+
+    for entry in walker {
+        let entry = entry.map_err(|e| io::Error::other(e.to_string()))?;
+        let path = entry.path();
+        if entry.file_type().is_some_and(|t| t.is_file()) && has_wanted_extension(path, config) {
+            files.push(path.to_path_buf());
         }
     }
 
     Ok(())
 }
 
+fn has_wanted_extension(path: &Path, config: &Config) -> bool {
+    let ext = path
+        .extension()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_lowercase();
+    config.extensions.iter().any(|e| e.to_lowercase() == ext)
+}
+
+/// 处理文件列表；`--jobs` 大于 1 时多线程并行处理，输出仍按文件列表顺序打印。
+/// 每创建一个备份就立即追加到 `--dir` 下的备份清单中
+pub fn process_files(files: &[PathBuf], config: &Config, result: &mut RunResult) {
+    let root_dir = Path::new(&config.dir);
+    let manifest = backup::ManifestWriter::new(root_dir);
+    process_files_with(files, config, &manifest, result);
+    if let Err(e) = manifest.finish() {
+        result.errors.insert(manifest_path(root_dir), e);
+    }
+}
+
+fn process_files_with(
+    files: &[PathBuf],
+    config: &Config,
+    manifest: &backup::ManifestWriter,
+    result: &mut RunResult,
+) {
+    let jobs = config.worker_count().min(files.len());
+    if jobs <= 1 {
+        for path in files {
+            let (report, outcome) = inspect_file(path, config);
+            manifest.append(&report);
+            record_outcome(report, outcome, config, result);
+        }
+        return;
+    }
+
+    let next_index = AtomicUsize::new(0);
+    let (tx, rx) = mpsc::channel();
+    thread::scope(|scope| {
+        for _ in 0..jobs {
+            let tx = tx.clone();
+            let next_index = &next_index;
+            scope.spawn(move || loop {
+                let index = next_index.fetch_add(1, Ordering::Relaxed);
+                let Some(path) = files.get(index) else {
+                    break;
+                };
+                let (report, outcome) = inspect_file(path, config);
+                manifest.append(&report);
+                if tx.send((index, (report, outcome))).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(tx);
+
+        // 结果可能乱序到达，先缓存，再按文件顺序依次输出和统计
+        let mut pending = BTreeMap::new();
+        let mut next_to_print = 0;
+        for (index, processed) in rx {
+            pending.insert(index, processed);
+            while let Some((report, outcome)) = pending.remove(&next_to_print) {
+                record_outcome(report, outcome, config, result);
+                next_to_print += 1;
+            }
+        }
+    });
+}
+
+/// 输出单个文件的结果，并合并到统计、错误和报告列表中
+fn record_outcome(
+    report: FileReport,
+    outcome: io::Result<FileProcessOutcome>,
+    config: &Config,
+    result: &mut RunResult,
+) {
+    match config.format {
+        OutputFormat::Text => print!("{}", report::render_text(&report, config)),
+        OutputFormat::Ndjson => println!("{}", report::render_ndjson_file(&report)),
+        OutputFormat::Json => {}
+    }
+
+    match outcome {
+        Ok(FileProcessOutcome::Converted) => result.stats.converted += 1,
+        Ok(FileProcessOutcome::NoConversion) => result.stats.no_conversion += 1,
+        Ok(FileProcessOutcome::CheckFailed) => result.stats.check_failed += 1,
+        Err(e) => {
+            result.stats.failed += 1;
+            result.errors.insert(report.path.clone(), e);
+        }
+    }
+    result.reports.push(report);
+}
+
 pub fn run(config: &Config) -> io::Result<RunResult> {
+    config.source_encodings()?;
+    config.target_encoding()?;
     let root_dir = PathBuf::from(&config.dir);
     let ignore_matcher = build_ignore_matcher(&root_dir, config)?;
-    let mut errors = HashMap::new();
-    let mut stats = ProcessingStats::default();
-
-    process_files_in_dir(
-        &root_dir,
-        &root_dir,
-        config,
-        &ignore_matcher,
-        &mut errors,
-        &mut stats,
-    )?;
-    Ok(RunResult { errors, stats })
+
+    let mut files = Vec::new();
+    if !config.paths.is_empty() {
+        files = collect_paths(&root_dir, &config.paths, config, &ignore_matcher)?;
+    } else if config.gitignore {
+        collect_files_gitignore(&root_dir, &root_dir, config, &ignore_matcher, &mut files)?;
+    } else {
+        collect_files(&root_dir, &root_dir, config, &ignore_matcher, &mut files)?;
+    }
+
+    let mut result = RunResult::default();
+    process_files(&files, config, &mut result);
+    Ok(result)
+}
+
+/// 按 `--format` 渲染整次运行的 JSON 文档或 NDJSON 汇总行；文本格式返回 `None`
+pub fn render_summary(result: &RunResult, config: &Config) -> Option<String> {
+    match config.format {
+        OutputFormat::Text => None,
+        OutputFormat::Json => Some(report::render_json(&result.reports, &result.stats)),
+        OutputFormat::Ndjson => Some(report::render_ndjson_summary(&result.stats)),
+    }
 }
@@ -1,15 +1,18 @@
-use clap::Parser;
-use gbk2utf8::{run, Config, UiLang};
+use clap::{CommandFactory, FromArgMatches};
+use gbk2utf8::{
+    clean_backups, convert_stdin, render_summary, restore_backups, run, BackupRunResult, Command,
+    Config, FileProcessOutcome, UiLang,
+};
 use std::process;
 
+/// `--check` 发现非目标编码文件时的退出码
+const EXIT_CHECK_FAILED: i32 = 3;
+
 mod built_info {
     include!(concat!(env!("OUT_DIR"), "/built.rs"));
 }
 
-fn main() {
-    let config = Config::parse();
-    let is_zh = matches!(config.ui_lang(), UiLang::Zh);
-
+fn print_banner(is_zh: bool) {
     if is_zh {
         println!(
             "版本 {}，编译于 [{}]，由 {} 构建（目标: {}）",
@@ -27,6 +30,91 @@ fn main() {
             built_info::TARGET
         );
     }
+}
+
+/// 执行 `restore` / `clean-backups` 子命令并输出结果
+fn run_backup_command(command: Command, config: &Config, is_zh: bool) {
+    let result = match command {
+        Command::Restore => restore_backups(config),
+        Command::CleanBackups => clean_backups(config),
+    };
+    let BackupRunResult { processed, errors } = match result {
+        Ok(result) => result,
+        Err(e) => {
+            if is_zh {
+                eprintln!("❌ 读取备份清单失败: {}", e);
+            } else {
+                eprintln!("❌ failed to read backup manifest: {}", e);
+            }
+            process::exit(1);
+        }
+    };
+
+    if !errors.is_empty() {
+        if is_zh {
+            println!("\n以下文件处理失败：");
+        } else {
+            println!("\nfailed to process these files:");
+        }
+        let mut errors: Vec<_> = errors.iter().collect();
+        errors.sort_by(|a, b| a.0.cmp(b.0));
+        for (path, err) in errors {
+            println!("{}: {}", path.display(), err);
+        }
+        process::exit(2);
+    }
+
+    match (command, is_zh) {
+        (Command::Restore, true) => println!("✅ 已恢复 {} 个文件", processed.len()),
+        (Command::Restore, false) => println!("✅ restored {} file(s)", processed.len()),
+        (Command::CleanBackups, true) => println!("✅ 已删除 {} 个备份", processed.len()),
+        (Command::CleanBackups, false) => println!("✅ removed {} backup(s)", processed.len()),
+    }
+}
+
+fn main() {
+    let matches = Config::command().get_matches();
+    let mut config = Config::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+
+    if let Err(e) = config.load_project_config(&matches) {
+        if matches!(config.ui_lang(), UiLang::Zh) {
+            eprintln!("❌ 读取配置文件失败: {}", e);
+        } else {
+            eprintln!("❌ failed to load config file: {}", e);
+        }
+        process::exit(1);
+    }
+    let is_zh = matches!(config.ui_lang(), UiLang::Zh);
+
+    if config.print_config {
+        print!("{}", config.render_project_config());
+        return;
+    }
+
+    if let Some(command) = config.command {
+        run_backup_command(command, &config, is_zh);
+        return;
+    }
+
+    if config.is_stdin() {
+        match convert_stdin(&config) {
+            Ok(FileProcessOutcome::CheckFailed) => process::exit(EXIT_CHECK_FAILED),
+            Ok(_) => {}
+            Err(e) => {
+                if is_zh {
+                    eprintln!("❌ 转换标准输入失败: {}", e);
+                } else {
+                    eprintln!("❌ failed to convert stdin: {}", e);
+                }
+                process::exit(1);
+            }
+        }
+        return;
+    }
+
+    if config.is_text_output() && !config.check {
+        print_banner(is_zh);
+    }
 
     let result = match run(&config) {
         Ok(result) => result,
@@ -40,13 +128,44 @@ fn main() {
         }
     };
 
+    if let Some(summary) = render_summary(&result, &config) {
+        println!("{}", summary);
+        if !result.errors.is_empty() {
+            process::exit(2);
+        }
+        if result.stats.check_failed > 0 {
+            process::exit(EXIT_CHECK_FAILED);
+        }
+        return;
+    }
+
+    if config.check && result.errors.is_empty() {
+        if result.stats.check_failed > 0 {
+            if is_zh {
+                eprintln!(
+                    "❌ 发现 {} 个非 {} 编码的文件",
+                    result.stats.check_failed, config.to
+                );
+            } else {
+                eprintln!(
+                    "❌ found {} file(s) not encoded as {}",
+                    result.stats.check_failed, config.to
+                );
+            }
+            process::exit(EXIT_CHECK_FAILED);
+        }
+        return;
+    }
+
     if !result.errors.is_empty() {
         if is_zh {
             println!("\n以下文件转换失败：");
         } else {
             println!("\nfailed to convert these files:");
         }
-        for (path, err) in &result.errors {
+        let mut errors: Vec<_> = result.errors.iter().collect();
+        errors.sort_by(|a, b| a.0.cmp(b.0));
+        for (path, err) in errors {
             println!("{}: {}", path.display(), err);
         }
         process::exit(2);
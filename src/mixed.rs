@@ -0,0 +1,106 @@
+use crate::decode_bytes;
+use encoding_rs::{Encoding, UTF_8};
+use serde::Serialize;
+use std::io;
+
+const UTF_8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// 混合编码文件在报告中使用的编码名
+pub(crate) const MIXED: &str = "mixed";
+
+/// 混合编码文件中连续使用同一编码的行范围，行号从 1 开始，包含首尾两行
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct EncodingSegment {
+    pub encoding: String,
+    pub start_line: usize,
+    pub end_line: usize,
+}
+
+/// 逐行识别的结果：每行记录所用的编码，纯 ASCII 行归入前一行（开头的归入第一个非 ASCII 行）
+pub(crate) struct MixedLines<'a> {
+    lines: Vec<(&'a [u8], &'static Encoding)>,
+    /// 文件是否以 UTF-8 BOM 开头
+    pub(crate) bom: bool,
+}
+
+/// 逐行判断编码：合法 UTF-8 的行视为 UTF-8，否则依次尝试 `--from` 中的 ASCII 兼容编码严格解码。
+/// 只有同时存在 UTF-8 行和其他编码的行、且每行都能解码时才返回结果
+pub(crate) fn analyze<'a>(
+    content: &'a [u8],
+    sources: &[&'static Encoding],
+) -> Option<MixedLines<'a>> {
+    let (bom, body) = match content.strip_prefix(UTF_8_BOM) {
+        Some(rest) => (true, rest),
+        None => (false, content),
+    };
+    let legacy: Vec<_> = sources
+        .iter()
+        .copied()
+        .filter(|&encoding| encoding != UTF_8 && encoding.is_ascii_compatible())
+        .collect();
+
+    let mut classified = Vec::new();
+    for line in body.split_inclusive(|&b| b == b'\n') {
+        let encoding = if line.is_ascii() {
+            None
+        } else if std::str::from_utf8(line).is_ok() {
+            Some(UTF_8)
+        } else {
+            let encoding = legacy.iter().find(|encoding| {
+                encoding
+                    .decode_without_bom_handling_and_without_replacement(line)
+                    .is_some()
+            })?;
+            Some(*encoding)
+        };
+        classified.push((line, encoding));
+    }
+
+    let has_utf8 = classified
+        .iter()
+        .any(|&(_, encoding)| encoding == Some(UTF_8));
+    let has_legacy = classified
+        .iter()
+        .any(|&(_, encoding)| encoding.is_some_and(|encoding| encoding != UTF_8));
+    if !has_utf8 || !has_legacy {
+        return None;
+    }
+
+    let mut current = classified.iter().find_map(|&(_, encoding)| encoding)?;
+    let lines = classified
+        .into_iter()
+        .map(|(line, encoding)| {
+            current = encoding.unwrap_or(current);
+            (line, current)
+        })
+        .collect();
+    Some(MixedLines { lines, bom })
+}
+
+impl MixedLines<'_> {
+    /// 合并相邻同编码的行，得到各编码的行范围
+    pub(crate) fn segments(&self) -> Vec<EncodingSegment> {
+        let mut segments: Vec<EncodingSegment> = Vec::new();
+        for (i, (_, encoding)) in self.lines.iter().enumerate() {
+            let name = encoding.name().to_lowercase();
+            match segments.last_mut() {
+                Some(last) if last.encoding == name => last.end_line = i + 1,
+                _ => segments.push(EncodingSegment {
+                    encoding: name,
+                    start_line: i + 1,
+                    end_line: i + 1,
+                }),
+            }
+        }
+        segments
+    }
+
+    /// 每行按各自的编码解码后拼接，不含 BOM
+    pub(crate) fn decode(&self) -> io::Result<String> {
+        let mut text = String::new();
+        for (line, encoding) in &self.lines {
+            text.push_str(&decode_bytes(line, encoding)?);
+        }
+        Ok(text)
+    }
+}
@@ -0,0 +1,334 @@
+use crate::{
+    parse_encoding, BomOption, Config, EolOption, LangOption, MixedOption, OutputFormat,
+};
+use clap::parser::ValueSource;
+use clap::ArgMatches;
+use encoding_rs::Encoding;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// 配置文件中表示“跳过该路径”的特殊编码值
+const SKIP: &str = "skip";
+
+/// `.gbk2utf8.toml` 或 `Cargo.toml` 中 `[package.metadata.gbk2utf8]` 的内容。
+/// 键名与命令行长参数名相同；`--dir`、`--config` 和路径参数决定配置文件的位置，不能写在文件里
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub(crate) struct ProjectFile {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    show_info: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    scan_only: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dry_run: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    diff: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    check: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    backup: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    backup_dir: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    preserve_metadata: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    extensions: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    min_confidence: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    from: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    to: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    eol: Option<EolOption>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    bom: Option<BomOption>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mixed: Option<MixedOption>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tld: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    candidates: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    jobs: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ignore_file: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    gitignore: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    format: Option<OutputFormat>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    lang: Option<LangOption>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    overrides: Vec<OverrideEntry>,
+}
+
+/// 一条 `[[overrides]]` 规则
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct OverrideEntry {
+    /// gitignore 风格的路径模式，相对于 `--dir`
+    path: String,
+    /// 强制使用的源编码，或 `skip`
+    encoding: String,
+}
+
+/// 按路径覆盖编码识别结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathOverride {
+    /// 不识别编码，直接按指定的源编码处理
+    Encoding(&'static Encoding),
+    /// 跳过该路径
+    Skip,
+}
+
+/// 配置文件中的全部路径覆盖规则，按书写顺序保存，后面的规则优先
+#[derive(Debug, Clone, Default)]
+pub struct PathOverrides {
+    root: PathBuf,
+    rules: Vec<OverrideRule>,
+}
+
+#[derive(Debug, Clone)]
+struct OverrideRule {
+    pattern: String,
+    matcher: Gitignore,
+    action: PathOverride,
+}
+
+impl PathOverrides {
+    /// 读取配置文件中的 `[[overrides]]` 规则，路径模式相对于 `root_dir`
+    pub fn load(root_dir: &Path, config_file: &Path) -> io::Result<Self> {
+        let project = ProjectFile::read(config_file)?.unwrap_or_default();
+        Self::build(root_dir, config_file, &project.overrides)
+    }
+
+    fn build(root_dir: &Path, config_file: &Path, entries: &[OverrideEntry]) -> io::Result<Self> {
+        let mut rules = Vec::with_capacity(entries.len());
+        for entry in entries {
+            let action = if entry.encoding.eq_ignore_ascii_case(SKIP) {
+                PathOverride::Skip
+            } else {
+                PathOverride::Encoding(parse_encoding(&entry.encoding)?)
+            };
+
+            let matcher = GitignoreBuilder::new(root_dir)
+                .add_line(Some(config_file.to_path_buf()), &entry.path)
+                .and_then(|builder| builder.build())
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))?;
+            rules.push(OverrideRule {
+                pattern: entry.path.clone(),
+                matcher,
+                action,
+            });
+        }
+
+        Ok(Self {
+            root: root_dir.to_path_buf(),
+            rules,
+        })
+    }
+
+    /// 查找路径对应的覆盖规则，多条规则命中时以最后一条为准
+    pub fn lookup(&self, path: &Path) -> Option<PathOverride> {
+        let relative_path = path.strip_prefix(&self.root).unwrap_or(path);
+        // 根目录之外的绝对路径不受覆盖规则约束
+        if relative_path.has_root() {
+            return None;
+        }
+        self.rules
+            .iter()
+            .rev()
+            .find(|rule| {
+                rule.matcher
+                    .matched_path_or_any_parents(relative_path, false)
+                    .is_ignore()
+            })
+            .map(|rule| rule.action)
+    }
+
+    fn entries(&self) -> Vec<OverrideEntry> {
+        self.rules
+            .iter()
+            .map(|rule| OverrideEntry {
+                path: rule.pattern.clone(),
+                encoding: match rule.action {
+                    PathOverride::Encoding(encoding) => encoding.name().to_lowercase(),
+                    PathOverride::Skip => SKIP.to_string(),
+                },
+            })
+            .collect()
+    }
+}
+
+impl ProjectFile {
+    /// 读取配置文件；`Cargo.toml` 只取 `[package.metadata.gbk2utf8]`，没有该段时返回 `None`
+    fn read(path: &Path) -> io::Result<Option<Self>> {
+        let content = fs::read_to_string(path)?;
+        let invalid = |e: toml::de::Error| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{}: {}", path.display(), e),
+            )
+        };
+
+        if path.file_name().is_some_and(|name| name == "Cargo.toml") {
+            let manifest: toml::Table = toml::from_str(&content).map_err(invalid)?;
+            let section = manifest
+                .get("package")
+                .and_then(|package| package.get("metadata"))
+                .and_then(|metadata| metadata.get("gbk2utf8"));
+            return match section {
+                Some(section) => section.clone().try_into().map(Some).map_err(invalid),
+                None => Ok(None),
+            };
+        }
+        toml::from_str(&content).map(Some).map_err(invalid)
+    }
+
+    /// 记录合并后的实际设置，用于 `--print-config`
+    fn from_config(config: &Config) -> Self {
+        Self {
+            show_info: Some(config.show_info),
+            scan_only: Some(config.scan_only),
+            dry_run: Some(config.dry_run),
+            diff: Some(config.diff),
+            check: Some(config.check),
+            backup: Some(config.backup),
+            backup_dir: config.backup_dir.clone(),
+            preserve_metadata: Some(config.preserve_metadata),
+            extensions: Some(config.extensions.clone()),
+            min_confidence: Some(config.min_confidence),
+            from: Some(config.from.clone()),
+            to: Some(config.to.clone()),
+            eol: Some(config.eol),
+            bom: Some(config.bom),
+            mixed: Some(config.mixed),
+            tld: config.tld.clone(),
+            candidates: Some(config.candidates),
+            jobs: Some(config.jobs),
+            ignore_file: Some(config.ignore_file.clone()),
+            gitignore: Some(config.gitignore),
+            format: Some(config.format),
+            lang: Some(config.lang),
+            overrides: config.overrides.entries(),
+        }
+    }
+}
+
+/// 查找项目配置文件：优先使用 `--config`（默认 `.gbk2utf8.toml`），
+/// 未显式指定且默认文件不存在时回退到 `--dir` 下 `Cargo.toml` 的 `[package.metadata.gbk2utf8]`
+fn find_project_file(
+    config: &Config,
+    matches: &ArgMatches,
+) -> io::Result<Option<(PathBuf, ProjectFile)>> {
+    let root_dir = Path::new(&config.dir);
+    let config_file = crate::resolve_path_in_dir(root_dir, &config.config_file);
+    if config_file.exists() || from_command_line(matches, "config_file") {
+        return Ok(ProjectFile::read(&config_file)?.map(|project| (config_file, project)));
+    }
+
+    let manifest = root_dir.join("Cargo.toml");
+    if manifest.exists() {
+        return Ok(ProjectFile::read(&manifest)?.map(|project| (manifest, project)));
+    }
+    Ok(None)
+}
+
+fn from_command_line(matches: &ArgMatches, id: &str) -> bool {
+    matches.value_source(id) == Some(ValueSource::CommandLine)
+}
+
+/// 只在参数未通过命令行显式指定时采用配置文件中的值
+fn merge<T>(target: &mut T, value: Option<T>, matches: &ArgMatches, id: &str) {
+    if let Some(value) = value {
+        if !from_command_line(matches, id) {
+            *target = value;
+        }
+    }
+}
+
+impl Config {
+    /// 加载项目配置文件并与命令行参数合并：命令行显式给出的参数优先，其余参数取配置文件中的值，
+    /// 两者都没有时保留默认值。同时加载 `[[overrides]]` 路径覆盖规则
+    pub fn load_project_config(&mut self, matches: &ArgMatches) -> io::Result<()> {
+        if let Some((path, project)) = find_project_file(self, matches)? {
+            self.apply_project_file(path, project, matches)?;
+        }
+
+        // --diff 与 --dry-run 可能分别来自命令行和配置文件，合并后再校验
+        if self.diff && !self.dry_run {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "--diff requires --dry-run",
+            ));
+        }
+        Ok(())
+    }
+
+    fn apply_project_file(
+        &mut self,
+        path: PathBuf,
+        project: ProjectFile,
+        matches: &ArgMatches,
+    ) -> io::Result<()> {
+        merge(&mut self.show_info, project.show_info, matches, "show_info");
+        merge(&mut self.scan_only, project.scan_only, matches, "scan_only");
+        merge(&mut self.dry_run, project.dry_run, matches, "dry_run");
+        merge(&mut self.diff, project.diff, matches, "diff");
+        merge(&mut self.check, project.check, matches, "check");
+        merge(&mut self.backup, project.backup, matches, "backup");
+        merge(
+            &mut self.backup_dir,
+            project.backup_dir.map(Some),
+            matches,
+            "backup_dir",
+        );
+        merge(
+            &mut self.preserve_metadata,
+            project.preserve_metadata,
+            matches,
+            "preserve_metadata",
+        );
+        merge(&mut self.extensions, project.extensions, matches, "extensions");
+        merge(
+            &mut self.min_confidence,
+            project.min_confidence,
+            matches,
+            "min_confidence",
+        );
+        merge(&mut self.from, project.from, matches, "from");
+        merge(&mut self.to, project.to, matches, "to");
+        merge(&mut self.eol, project.eol, matches, "eol");
+        merge(&mut self.bom, project.bom, matches, "bom");
+        merge(&mut self.mixed, project.mixed, matches, "mixed");
+        merge(&mut self.tld, project.tld.map(Some), matches, "tld");
+        merge(&mut self.candidates, project.candidates, matches, "candidates");
+        merge(&mut self.jobs, project.jobs, matches, "jobs");
+        merge(&mut self.ignore_file, project.ignore_file, matches, "ignore_file");
+        merge(&mut self.gitignore, project.gitignore, matches, "gitignore");
+        merge(&mut self.format, project.format, matches, "format");
+        merge(&mut self.lang, project.lang, matches, "lang");
+
+        self.overrides = PathOverrides::build(Path::new(&self.dir), &path, &project.overrides)?;
+        self.project_config = Some(path);
+        Ok(())
+    }
+
+    /// 以 TOML 格式输出合并后的实际设置，可直接保存为 `.gbk2utf8.toml`
+    pub fn render_project_config(&self) -> String {
+        let source = match &self.project_config {
+            Some(path) => path.display().to_string(),
+            None => "-".to_string(),
+        };
+        format!(
+            "# dir = {}\n# config = {}\n{}",
+            self.dir,
+            source,
+            toml::to_string(&ProjectFile::from_config(self)).expect("serialize project config")
+        )
+    }
+}
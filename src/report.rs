@@ -0,0 +1,352 @@
+use crate::{
+    tr, BomOption, Config, EncodingSegment, EolOption, LineEndings, ProcessingStats, UiLang,
+};
+use serde::{Serialize, Serializer};
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+/// 单个文件的处理动作
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FileAction {
+    /// 已经是目标编码，无需转换
+    Unchanged,
+    /// 已转换为目标编码
+    Converted,
+    /// 需要转换，但处于扫描模式未写入
+    ScanOnly,
+    /// 需要转换，但处于试运行模式未写入
+    WouldConvert,
+    /// 编码不在 `--from` 允许列表中，跳过
+    Skipped,
+    /// 编码不确定或置信度不足，跳过
+    Uncertain,
+    /// 项目配置文件的覆盖规则指定跳过
+    Excluded,
+    /// 二进制内容，跳过
+    Binary,
+    /// 混合编码（`--mixed report`），只报告各编码的行范围，跳过
+    Mixed,
+    /// 检查模式下发现非目标编码的文件
+    CheckFailed,
+    /// 处理失败
+    Failed,
+}
+
+/// 单个文件的处理结果，用于文本输出和 JSON/NDJSON 报告
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FileReport {
+    #[serde(serialize_with = "serialize_path")]
+    pub path: PathBuf,
+    pub encoding: Option<String>,
+    pub confidence: Option<f64>,
+    pub action: FileAction,
+    pub target: Option<String>,
+    #[serde(serialize_with = "serialize_optional_path")]
+    pub backup: Option<PathBuf>,
+    pub error: Option<String>,
+    /// 源文件是否以 BOM 开头
+    pub bom: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line_endings: Option<LineEndings>,
+    /// `--mixed` 识别出的混合编码文件中各编码的行范围
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub segments: Vec<EncodingSegment>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub diff: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub candidates: Vec<EncodingCandidate>,
+}
+
+/// `--candidates` 列出的候选编码
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EncodingCandidate {
+    pub encoding: String,
+    /// 严格解码结果的合理程度（考虑其他中文候选编码的竞争），范围 0~1
+    pub score: f64,
+    /// 非法字节序列的数量
+    pub errors: usize,
+    /// 第一行含非 ASCII 字符的解码结果
+    pub sample: String,
+}
+
+impl FileReport {
+    pub fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            encoding: None,
+            confidence: None,
+            action: FileAction::Uncertain,
+            target: None,
+            backup: None,
+            error: None,
+            bom: false,
+            line_endings: None,
+            segments: Vec::new(),
+            diff: None,
+            candidates: Vec::new(),
+        }
+    }
+}
+
+fn serialize_path<S: Serializer>(path: &Path, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&path.to_string_lossy())
+}
+
+fn serialize_optional_path<S: Serializer>(
+    path: &Option<PathBuf>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match path {
+        Some(path) => serialize_path(path, serializer),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// `--format json` 输出的完整文档
+#[derive(Serialize)]
+struct JsonReport<'a> {
+    files: &'a [FileReport],
+    summary: &'a ProcessingStats,
+}
+
+/// `--format ndjson` 输出的每一行记录
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum NdjsonRecord<'a> {
+    File(&'a FileReport),
+    Summary(&'a ProcessingStats),
+}
+
+pub(crate) fn render_json(reports: &[FileReport], stats: &ProcessingStats) -> String {
+    serde_json::to_string_pretty(&JsonReport {
+        files: reports,
+        summary: stats,
+    })
+    .expect("serialize json report")
+}
+
+pub(crate) fn render_ndjson_file(report: &FileReport) -> String {
+    serde_json::to_string(&NdjsonRecord::File(report)).expect("serialize ndjson record")
+}
+
+pub(crate) fn render_ndjson_summary(stats: &ProcessingStats) -> String {
+    serde_json::to_string(&NdjsonRecord::Summary(stats)).expect("serialize ndjson summary")
+}
+
+/// 按原有的 emoji 文本格式渲染单个文件的处理结果
+pub(crate) fn render_text(report: &FileReport, config: &Config) -> String {
+    let mut out = String::new();
+    let path = report.path.display();
+    let encoding = report.encoding.as_deref().unwrap_or_default();
+    let target = report.target.as_deref().unwrap_or_default();
+    // 已是目标编码、只改写换行符或 BOM 的文件
+    let layout_only = encoding == target;
+    let layout = describe_layout_changes(report, config);
+
+    // 检查模式只输出紧凑的 path:encoding，便于 CI 和编辑器定位
+    if config.check {
+        if report.action == FileAction::CheckFailed {
+            let _ = writeln!(out, "{}:{}", path, encoding);
+        }
+        return out;
+    }
+
+    let (prefix, msg) = match report.action {
+        FileAction::Failed | FileAction::CheckFailed => return out,
+        // -i 时与其他结果一样给出识别的编码和置信度，便于调整 --min-confidence
+        FileAction::Uncertain if config.show_info => (
+            "⚠️",
+            tr(
+                config,
+                "，编码不确定或置信度不足，跳过",
+                " (uncertain encoding or low confidence, skipped)",
+            )
+            .to_string(),
+        ),
+        FileAction::Uncertain => {
+            let _ = writeln!(
+                out,
+                "⚠️ {}: {}",
+                path,
+                tr(
+                    config,
+                    "编码不确定或置信度不足，跳过",
+                    "uncertain encoding or low confidence, skipped"
+                )
+            );
+            render_candidates(&mut out, report, config);
+            return out;
+        }
+        FileAction::Excluded => {
+            let _ = writeln!(
+                out,
+                "⏭️ {}: {}",
+                path,
+                tr(config, "配置文件指定跳过", "skipped by config overrides")
+            );
+            return out;
+        }
+        FileAction::Binary => {
+            let _ = writeln!(
+                out,
+                "⏭️ {}: {}",
+                path,
+                tr(config, "二进制文件，跳过", "binary file, skipped")
+            );
+            return out;
+        }
+        FileAction::Mixed => {
+            let _ = writeln!(
+                out,
+                "⚠️ {}: {}",
+                path,
+                tr(
+                    config,
+                    "混合编码，跳过（--mixed fix 可逐段修复）",
+                    "mixed encodings, skipped (use --mixed fix to repair per segment)"
+                )
+            );
+            render_segments(&mut out, report, config);
+            return out;
+        }
+        FileAction::Unchanged => ("✅", String::new()),
+        FileAction::ScanOnly => (
+            "⏩",
+            tr(config, "，未转换（扫描模式）", " (not converted, scan-only mode)").to_string(),
+        ),
+        FileAction::WouldConvert => (
+            "⏩",
+            match (config.ui_lang(), layout_only) {
+                (UiLang::Zh, false) => format!("，将转换为 {}（试运行，未写入）", target),
+                (UiLang::En, false) => format!(" (would convert to {}, dry run)", target),
+                (UiLang::Zh, true) => format!("，将改写：{}（试运行，未写入）", layout),
+                (UiLang::En, true) => format!(" (would rewrite: {}, dry run)", layout),
+            },
+        ),
+        FileAction::Converted => {
+            if let (Some(bak), true) = (&report.backup, config.show_info) {
+                let _ = writeln!(
+                    out,
+                    "📦 {}: {}",
+                    tr(config, "备份创建", "backup created"),
+                    bak.display()
+                );
+            }
+            (
+                "🔄",
+                match (config.ui_lang(), layout_only) {
+                    (UiLang::Zh, false) => format!("，已转换为 {}", target),
+                    (UiLang::En, false) => format!(" (converted to {})", target),
+                    (UiLang::Zh, true) => format!("，已改写：{}", layout),
+                    (UiLang::En, true) => format!(" (rewritten: {})", layout),
+                },
+            )
+        }
+        FileAction::Skipped => ("❌", tr(config, "，跳过", " (skipped)").to_string()),
+    };
+
+    if config.show_info {
+        let _ = writeln!(
+            out,
+            "{} {}: {} = {}, {} = {:.2}{}",
+            prefix,
+            path,
+            tr(config, "编码", "encoding"),
+            encoding,
+            tr(config, "置信度", "confidence"),
+            report.confidence.unwrap_or_default(),
+            msg
+        );
+    } else {
+        let _ = writeln!(
+            out,
+            "{} {}: {} = {}{}",
+            prefix,
+            path,
+            tr(config, "编码", "encoding"),
+            encoding,
+            msg
+        );
+    }
+
+    render_segments(&mut out, report, config);
+    render_mixed_line_endings(&mut out, report, config);
+    render_candidates(&mut out, report, config);
+    if let Some(diff) = &report.diff {
+        out.push_str(diff);
+    }
+
+    out
+}
+
+/// 描述 `--eol`、`--bom` 对已是目标编码的文件所做的改写
+fn describe_layout_changes(report: &FileReport, config: &Config) -> String {
+    let mut changes = Vec::new();
+    if report
+        .line_endings
+        .is_some_and(|line_endings| line_endings.needs_fix(config.eol))
+    {
+        changes.push(match config.eol {
+            EolOption::Crlf => tr(config, "换行符改为 CRLF", "line endings to CRLF"),
+            _ => tr(config, "换行符改为 LF", "line endings to LF"),
+        });
+    }
+    match (config.bom, report.bom) {
+        (BomOption::Add, false) => changes.push(tr(config, "添加 BOM", "add BOM")),
+        (BomOption::Strip, true) => changes.push(tr(config, "去掉 BOM", "strip BOM")),
+        _ => {}
+    }
+    changes.join(tr(config, "，", ", "))
+}
+
+fn render_mixed_line_endings(out: &mut String, report: &FileReport, config: &Config) {
+    let Some(line_endings) = report.line_endings.filter(LineEndings::is_mixed) else {
+        return;
+    };
+    let counts: Vec<String> = [
+        ("CRLF", line_endings.crlf),
+        ("LF", line_endings.lf),
+        ("CR", line_endings.cr),
+    ]
+    .iter()
+    .filter(|(_, n)| *n > 0)
+    .map(|(name, n)| format!("{} {}", name, n))
+    .collect();
+    let message = match config.ui_lang() {
+        UiLang::Zh => format!("换行符混用（{}）", counts.join("，")),
+        UiLang::En => format!("mixed line endings ({})", counts.join(", ")),
+    };
+    let _ = writeln!(out, "⚠️ {}: {}", report.path.display(), message);
+}
+
+fn render_segments(out: &mut String, report: &FileReport, config: &Config) {
+    for segment in &report.segments {
+        let lines = if segment.start_line == segment.end_line {
+            segment.start_line.to_string()
+        } else {
+            format!("{}-{}", segment.start_line, segment.end_line)
+        };
+        let _ = match config.ui_lang() {
+            UiLang::Zh => writeln!(out, "   第 {} 行：{}", lines, segment.encoding),
+            UiLang::En => writeln!(out, "   line {}: {}", lines, segment.encoding),
+        };
+    }
+}
+
+fn render_candidates(out: &mut String, report: &FileReport, config: &Config) {
+    for (i, candidate) in report.candidates.iter().enumerate() {
+        let _ = writeln!(
+            out,
+            "   {}. {}: {} = {:.2}, {} = {}, {}: {}",
+            i + 1,
+            candidate.encoding,
+            tr(config, "得分", "score"),
+            candidate.score,
+            tr(config, "解码错误", "decode errors"),
+            candidate.errors,
+            tr(config, "示例", "sample"),
+            candidate.sample
+        );
+    }
+}
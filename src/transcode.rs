@@ -0,0 +1,193 @@
+use crate::detect::{read_chunk, CHUNK_SIZE};
+use crate::eol::EolNormalizer;
+use crate::{BomOption, EolOption};
+use encoding_rs::{DecoderResult, EncoderResult, Encoding, UTF_8};
+use std::io::{self, Read, Write};
+
+/// UTF-8 的 BOM（U+FEFF）
+const UTF_8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// 流式转码：按块解码源编码，再编码为目标编码写入 `output`，内存占用与输入大小无关。
+/// 出现非法序列时立即失败；无法用目标编码表示的字符会全部收集后一并报告。
+/// 源文件的 BOM 不会出现在输出中，是否写入 BOM 由 `bom` 决定；换行符按 `eol` 改写。
+/// 返回改写的换行符和 BOM 的数量
+pub(crate) fn transcode<R: Read, W: Write>(
+    input: &mut R,
+    output: &mut W,
+    source: &'static Encoding,
+    target: &'static Encoding,
+    eol: EolOption,
+    bom: BomOption,
+) -> io::Result<usize> {
+    let mut decoder = source.new_decoder_without_bom_handling();
+    let mut encoder = TrackingEncoder::new(target);
+    let mut normalizer = EolNormalizer::new(eol);
+    let mut buf = vec![0u8; CHUNK_SIZE];
+    let mut text = String::with_capacity(CHUNK_SIZE * 3 + 16);
+    let mut normalized = String::new();
+    let mut first = true;
+    let mut changed = 0;
+
+    loop {
+        let n = read_chunk(input, &mut buf)?;
+        let last = n == 0;
+        let mut src = &buf[..n];
+        if std::mem::take(&mut first) {
+            let had_bom = match Encoding::for_bom(src) {
+                Some((encoding, len)) if encoding == source => {
+                    src = &src[len..];
+                    true
+                }
+                _ => false,
+            };
+            let add_bom = writes_bom(target, bom, had_bom);
+            if add_bom {
+                output.write_all(UTF_8_BOM)?;
+            }
+            changed += usize::from(add_bom != had_bom);
+        }
+        loop {
+            text.clear();
+            let (result, read) = decoder.decode_to_string_without_replacement(src, &mut text, last);
+            src = &src[read..];
+            encoder.encode(normalizer.apply(&text, &mut normalized), output)?;
+            match result {
+                DecoderResult::InputEmpty => break,
+                DecoderResult::OutputFull => {}
+                DecoderResult::Malformed(_, _) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("{} decode failed", source.name()),
+                    ))
+                }
+            }
+        }
+        if last {
+            break;
+        }
+    }
+
+    encoder.encode(normalizer.finish(), output)?;
+    encoder.finish(output)?;
+    Ok(changed + normalizer.changed)
+}
+
+/// 按 `--bom` 和源文件是否有 BOM 决定输出是否以 BOM 开头；只有 UTF-8 目标编码才会写入 BOM
+pub(crate) fn writes_bom(target: &'static Encoding, bom: BomOption, had_bom: bool) -> bool {
+    target == UTF_8
+        && match bom {
+            BomOption::Keep => had_bom,
+            BomOption::Add => true,
+            BomOption::Strip => false,
+        }
+}
+
+/// 将文本编码为目标编码，存在无法表示的字符时逐个报告而不是替换为 `?`
+pub(crate) fn encode_text(text: &str, target: &'static Encoding) -> io::Result<Vec<u8>> {
+    let mut output = Vec::with_capacity(text.len());
+    let mut encoder = TrackingEncoder::new(target);
+    encoder.encode(text, &mut output)?;
+    encoder.finish(&mut output)?;
+    Ok(output)
+}
+
+/// 记录行列位置的目标编码器，用于报告无法表示的字符所在位置
+struct TrackingEncoder {
+    target: &'static Encoding,
+    encoder: Option<encoding_rs::Encoder>,
+    buffer: Vec<u8>,
+    position: Position,
+    unmappable: Vec<String>,
+}
+
+/// 当前编码到的行列位置（从 1 开始）
+struct Position {
+    line: usize,
+    column: usize,
+}
+
+impl Position {
+    fn advance(&mut self, text: &str) {
+        for c in text.chars() {
+            if c == '\n' {
+                self.line += 1;
+                self.column = 1;
+            } else {
+                self.column += 1;
+            }
+        }
+    }
+}
+
+impl TrackingEncoder {
+    fn new(target: &'static Encoding) -> Self {
+        Self {
+            target,
+            encoder: (target != UTF_8).then(|| target.new_encoder()),
+            buffer: Vec::with_capacity(CHUNK_SIZE * 2),
+            position: Position { line: 1, column: 1 },
+            unmappable: Vec::new(),
+        }
+    }
+
+    fn encode<W: Write>(&mut self, text: &str, output: &mut W) -> io::Result<()> {
+        let Some(encoder) = self.encoder.as_mut() else {
+            return output.write_all(text.as_bytes());
+        };
+
+        let mut consumed = 0;
+        loop {
+            self.buffer.clear();
+            let (result, read) = encoder.encode_from_utf8_to_vec_without_replacement(
+                &text[consumed..],
+                &mut self.buffer,
+                false,
+            );
+            output.write_all(&self.buffer)?;
+            let start = consumed;
+            consumed += read;
+            match result {
+                EncoderResult::InputEmpty => {
+                    self.position.advance(&text[start..consumed]);
+                    return Ok(());
+                }
+                EncoderResult::OutputFull => {
+                    self.position.advance(&text[start..consumed]);
+                    self.buffer.reserve(text.len() - consumed + 16);
+                }
+                EncoderResult::Unmappable(c) => {
+                    self.position.advance(&text[start..consumed - c.len_utf8()]);
+                    self.unmappable.push(format!(
+                        "'{}' (U+{:04X}) at {}:{}",
+                        c, c as u32, self.position.line, self.position.column
+                    ));
+                    self.position
+                        .advance(&text[consumed - c.len_utf8()..consumed]);
+                }
+            }
+        }
+    }
+
+    /// 刷新编码器状态；存在无法表示的字符时返回包含所有位置的错误
+    fn finish<W: Write>(mut self, output: &mut W) -> io::Result<()> {
+        if let Some(encoder) = self.encoder.as_mut() {
+            self.buffer.clear();
+            self.buffer.reserve(16);
+            let _ = encoder.encode_from_utf8_to_vec_without_replacement("", &mut self.buffer, true);
+            output.write_all(&self.buffer)?;
+        }
+
+        if self.unmappable.is_empty() {
+            return Ok(());
+        }
+        Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "{} character(s) cannot be encoded as {}: {}",
+                self.unmappable.len(),
+                self.target.name(),
+                self.unmappable.join(", ")
+            ),
+        ))
+    }
+}
@@ -0,0 +1,139 @@
+use crate::detect::{read_chunk, CHUNK_SIZE};
+use std::io::{self, Read};
+
+/// 判断是否为 UTF-32 时读取的文件开头字节数
+const SNIFF_BYTES: usize = 4096;
+
+/// encoding_rs 不支持的 UTF-32，由本模块自行识别和解码
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Utf32 {
+    Le,
+    Be,
+}
+
+impl Utf32 {
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Utf32::Le => "UTF-32LE",
+            Utf32::Be => "UTF-32BE",
+        }
+    }
+
+    fn unit(self, bytes: &[u8]) -> u32 {
+        let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        match self {
+            Utf32::Le => u32::from_le_bytes(bytes),
+            Utf32::Be => u32::from_be_bytes(bytes),
+        }
+    }
+
+    /// 按码元解码样本中的字符，非法码位和末尾不完整的码元忽略
+    pub(crate) fn chars(self, sample: &[u8]) -> impl Iterator<Item = char> + '_ {
+        sample
+            .chunks_exact(4)
+            .filter_map(move |bytes| char::from_u32(self.unit(bytes)))
+    }
+
+    fn decode_failed(self) -> io::Error {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} decode failed", self.name()),
+        )
+    }
+}
+
+/// 根据 BOM 或空字节分布判断样本是否为 UTF-32：没有 BOM 时，
+/// 要求每 4 个字节都是合法且非 NUL 的 Unicode 码位（GBK、UTF-8、UTF-16 文本几乎不可能满足）。
+/// `at_eof` 为假时样本末尾不完整的码元会被忽略
+pub(crate) fn sniff(sample: &[u8], at_eof: bool) -> Option<Utf32> {
+    if sample.starts_with(&[0xFF, 0xFE, 0x00, 0x00]) {
+        return Some(Utf32::Le);
+    }
+    if sample.starts_with(&[0x00, 0x00, 0xFE, 0xFF]) {
+        return Some(Utf32::Be);
+    }
+    if sample.len() < 4 || (at_eof && !sample.len().is_multiple_of(4)) {
+        return None;
+    }
+
+    let units = &sample[..sample.len() / 4 * 4];
+    [Utf32::Le, Utf32::Be].into_iter().find(|&kind| {
+        units.chunks_exact(4).all(|bytes| {
+            let unit = kind.unit(bytes);
+            unit != 0 && char::from_u32(unit).is_some()
+        })
+    })
+}
+
+/// 读取文件开头判断是否为 UTF-32
+pub(crate) fn sniff_reader<R: Read>(reader: &mut R) -> io::Result<Option<Utf32>> {
+    let mut head = Vec::with_capacity(SNIFF_BYTES);
+    reader.take(SNIFF_BYTES as u64).read_to_end(&mut head)?;
+    Ok(sniff(&head, head.len() < SNIFF_BYTES))
+}
+
+/// 将 UTF-32 字节流解码为 UTF-8 字节流，BOM 解码为 UTF-8 BOM；非法码位或不完整的码元返回错误
+pub(crate) struct Utf32Reader<R> {
+    inner: R,
+    kind: Utf32,
+    buf: Vec<u8>,
+    /// 上一块末尾不足 4 字节的码元
+    pending: Vec<u8>,
+    out: Vec<u8>,
+    out_pos: usize,
+    eof: bool,
+}
+
+impl<R: Read> Utf32Reader<R> {
+    pub(crate) fn new(inner: R, kind: Utf32) -> Self {
+        Self {
+            inner,
+            kind,
+            buf: vec![0u8; CHUNK_SIZE],
+            pending: Vec::new(),
+            out: Vec::new(),
+            out_pos: 0,
+            eof: false,
+        }
+    }
+
+    fn fill(&mut self) -> io::Result<()> {
+        self.out.clear();
+        self.out_pos = 0;
+        let n = read_chunk(&mut self.inner, &mut self.buf)?;
+        if n == 0 {
+            self.eof = true;
+            if !self.pending.is_empty() {
+                return Err(self.kind.decode_failed());
+            }
+            return Ok(());
+        }
+
+        self.pending.extend_from_slice(&self.buf[..n]);
+        let whole = self.pending.len() / 4 * 4;
+        let mut utf8 = [0u8; 4];
+        for bytes in self.pending[..whole].chunks_exact(4) {
+            let c =
+                char::from_u32(self.kind.unit(bytes)).ok_or_else(|| self.kind.decode_failed())?;
+            self.out
+                .extend_from_slice(c.encode_utf8(&mut utf8).as_bytes());
+        }
+        self.pending.drain(..whole);
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for Utf32Reader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.out_pos == self.out.len() {
+            if self.eof {
+                return Ok(0);
+            }
+            self.fill()?;
+        }
+        let n = buf.len().min(self.out.len() - self.out_pos);
+        buf[..n].copy_from_slice(&self.out[self.out_pos..self.out_pos + n]);
+        self.out_pos += n;
+        Ok(n)
+    }
+}
@@ -1,8 +1,12 @@
+use clap::{ArgMatches, CommandFactory, FromArgMatches};
 use encoding::all::GBK;
 use encoding::{EncoderTrap, Encoding};
 use gbk2utf8::{
-    build_ignore_matcher, convert_gbk_file, handle_file, process_files_in_dir, run,
-    scan_gbk_file, should_ignore, Config, FileProcessOutcome, LangOption, ProcessingStats,
+    build_ignore_matcher, clean_backups, convert_gbk_file, convert_stream, detect_encoding,
+    handle_file, inspect_file, manifest_path, process_files_in_dir, render_diff, render_summary,
+    restore_backups, run, scan_gbk_file, should_ignore, BackupEntry, BackupManifest, BomOption,
+    Config, EncodingSegment, EolOption, FileAction, FileProcessOutcome, LangOption, MixedOption,
+    OutputFormat, PathOverride, PathOverrides, ProcessingStats,
 };
 use std::collections::HashMap;
 use std::fs;
@@ -12,23 +16,55 @@ use tempfile::{tempdir, TempDir};
 
 fn make_config(dir: &Path) -> Config {
     Config {
+        command: None,
+        paths: Vec::new(),
         dir: dir.to_string_lossy().to_string(),
         show_info: false,
         scan_only: false,
+        dry_run: false,
+        check: false,
+        diff: false,
         backup: false,
+        backup_dir: None,
+        preserve_metadata: false,
         extensions: vec!["c".to_string(), "h".to_string(), "txt".to_string()],
         min_confidence: 0.8,
+        from: vec!["gbk".to_string(), "gb18030".to_string()],
+        to: "utf-8".to_string(),
+        eol: EolOption::Keep,
+        bom: BomOption::Keep,
+        mixed: MixedOption::Off,
         tld: Some("cn".to_string()),
+        candidates: 0,
+        jobs: 1,
         ignore_file: ".gbk2utf8ignore".to_string(),
+        config_file: ".gbk2utf8.toml".to_string(),
+        print_config: false,
+        gitignore: false,
+        format: OutputFormat::Text,
         lang: LangOption::Auto,
+        overrides: PathOverrides::default(),
+        project_config: None,
     }
 }
 
+/// 没有显式命令行参数时的解析结果，配置文件中的值全部生效
+fn no_cli_args() -> ArgMatches {
+    Config::command().get_matches_from(["gbk2utf8"])
+}
+
 fn gbk_bytes(content: &str) -> Vec<u8> {
     GBK.encode(content, EncoderTrap::Strict)
         .expect("encode test text to gbk")
 }
 
+/// 大部分内容是合法 GBK、末尾混入非法字节的文件：置信度足够高，但转换会失败
+fn truncated_gbk(content: &str) -> Vec<u8> {
+    let mut bytes = gbk_bytes(content);
+    bytes.push(0xFF);
+    bytes
+}
+
 struct TestProject {
     temp_dir: TempDir,
 }
@@ -241,3 +277,1350 @@ fn run_end_to_end_with_generated_files() {
     assert_eq!(fs::read(&ignored).expect("read ignored file"), ignored_before);
     assert_eq!(fs::read(&untouched).expect("read untouched file"), untouched_before);
 }
+
+// --from 允许列表之外的编码应跳过，加入列表后按检测到的编码转换
+#[test]
+fn handle_file_converts_encodings_from_allow_list() {
+    let project = TestProject::new();
+    let input =
+        "繁體中文內容用於編碼識別，包含足夠多的漢字來提高檢測準確度。繁體中文內容用於編碼識別。";
+    let (big5, _, _) = encoding_rs::BIG5.encode(input);
+    let file = project.write_bytes("legacy.c", &big5);
+
+    let mut config = make_config(project.root());
+    config.tld = Some("tw".to_string());
+    config.min_confidence = 0.5;
+
+    config.from = vec!["gbk".to_string()];
+    let outcome = handle_file(&file, &config).expect("handle big5 file without big5 allowed");
+    assert_eq!(outcome, FileProcessOutcome::NoConversion);
+    assert_eq!(
+        fs::read(&file).expect("read skipped big5 file"),
+        big5.to_vec()
+    );
+
+    config.from = vec!["gbk".to_string(), "big5".to_string()];
+    let outcome = handle_file(&file, &config).expect("handle big5 file with big5 allowed");
+    assert_eq!(outcome, FileProcessOutcome::Converted);
+    assert_eq!(
+        fs::read_to_string(&file).expect("read converted file"),
+        input
+    );
+}
+
+#[test]
+fn run_rejects_unknown_source_encoding() {
+    let project = TestProject::new();
+    let mut config = make_config(project.root());
+    config.from = vec!["not-an-encoding".to_string()];
+
+    let err = run(&config).expect_err("unknown --from label should fail");
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+}
+
+// 含四字节序列（扩展 B 区汉字、emoji）的文件应识别为 GB18030 并无损转换
+#[test]
+fn handle_file_detects_and_converts_gb18030_four_byte_sequences() {
+    let project = TestProject::new();
+    let input =
+        "中文内容用于编码识别，包含足够多的汉字来提高检测准确度。𠀀𪚥😀中文内容用于编码识别。";
+    let (gb18030, _, _) = encoding_rs::GB18030.encode(input);
+    let file = project.write_bytes("emoji.c", &gb18030);
+
+    let config = make_config(project.root());
+    let scanned = scan_gbk_file(&file, &config).expect("scan gb18030 file");
+    assert!(matches!(scanned, Some((ref name, _)) if name == "gb18030"));
+
+    let outcome = handle_file(&file, &config).expect("handle gb18030 file");
+    assert_eq!(outcome, FileProcessOutcome::Converted);
+    assert_eq!(
+        fs::read_to_string(&file).expect("read converted file"),
+        input
+    );
+}
+
+// --to gbk 时应将 UTF-8 文件反向转换为 GBK
+#[test]
+fn handle_file_converts_utf8_to_target_encoding() {
+    let project = TestProject::new();
+    let input = "反向转换为 GBK 的内容";
+    let file = project.write_utf8("keil.c", input);
+
+    let mut config = make_config(project.root());
+    config.to = "gbk".to_string();
+
+    let outcome = handle_file(&file, &config).expect("convert utf8 file to gbk");
+    assert_eq!(outcome, FileProcessOutcome::Converted);
+    assert_eq!(fs::read(&file).expect("read gbk file"), gbk_bytes(input));
+}
+
+// 目标编码无法表示的字符应逐个报告，且不修改原文件
+#[test]
+fn convert_to_target_encoding_reports_unmappable_characters() {
+    let project = TestProject::new();
+    let input = "第一行\n表情😀和𠀀";
+    let file = project.write_utf8("emoji.c", input);
+
+    let mut config = make_config(project.root());
+    config.to = "gbk".to_string();
+
+    let err = handle_file(&file, &config).expect_err("emoji cannot be encoded as gbk");
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    let message = err.to_string();
+    assert!(message.contains("U+1F600) at 2:3"), "{}", message);
+    assert!(message.contains("U+20000) at 2:5"), "{}", message);
+    assert_eq!(
+        fs::read_to_string(&file).expect("read untouched file"),
+        input
+    );
+}
+
+#[test]
+fn handle_file_dry_run_keeps_original_bytes() {
+    let project = TestProject::new();
+    let original = gbk_bytes("试运行不写入");
+    let file = project.write_bytes("dry_run.c", &original);
+
+    let mut config = make_config(project.root());
+    config.dry_run = true;
+    config.diff = true;
+
+    let outcome = handle_file(&file, &config).expect("handle file in dry run mode");
+    assert_eq!(outcome, FileProcessOutcome::NoConversion);
+    assert_eq!(fs::read(&file).expect("read file after dry run"), original);
+}
+
+// 差异输出应包含统一格式的块头和新旧行号
+#[test]
+fn render_diff_shows_changed_lines_with_line_numbers() {
+    let mut original = b"int main() {\n".to_vec();
+    original.extend(gbk_bytes("    // 中文注释\n"));
+    original.extend(b"}\n");
+    let converted = "int main() {\n    // 中文注释\n}\n";
+
+    let diff = render_diff(Path::new("src/main.c"), &original, converted);
+
+    assert!(
+        diff.starts_with("--- src/main.c\t(original)\n+++ src/main.c\t(converted)\n"),
+        "{}",
+        diff
+    );
+    assert!(diff.contains("@@ -1,3 +1,3 @@\n"), "{}", diff);
+    assert!(diff.contains("     1     1 | int main() {\n"), "{}", diff);
+    assert!(diff.contains("-    2       |     // \u{FFFD}"), "{}", diff);
+    assert!(
+        diff.contains("+          2 |     // 中文注释\n"),
+        "{}",
+        diff
+    );
+}
+
+// 原子写入后应保留原文件权限，且目录中不残留临时文件
+#[cfg(unix)]
+#[test]
+fn convert_gbk_file_writes_atomically_and_keeps_permissions() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let project = TestProject::new();
+    let input = "原子写入测试";
+    let file = project.write_gbk("script.txt", input);
+    fs::set_permissions(&file, fs::Permissions::from_mode(0o750)).expect("set permissions");
+
+    let config = make_config(project.root());
+    convert_gbk_file(&file, &config).expect("convert gbk file");
+
+    assert_eq!(
+        fs::read_to_string(&file).expect("read converted file"),
+        input
+    );
+    let mode = fs::metadata(&file)
+        .expect("read metadata")
+        .permissions()
+        .mode();
+    assert_eq!(mode & 0o777, 0o750);
+
+    let entries: Vec<_> = fs::read_dir(project.root())
+        .expect("read project dir")
+        .map(|entry| entry.expect("read dir entry").file_name())
+        .collect();
+    assert_eq!(entries, vec![std::ffi::OsString::from("script.txt")]);
+}
+
+// --preserve-metadata 应恢复原文件的修改时间和权限，避免触发 make 增量重编译
+#[test]
+fn convert_gbk_file_preserves_mtime_and_permissions() {
+    let project = TestProject::new();
+    let input = "保留元数据";
+    let file = project.write_gbk("keep_meta.c", input);
+    let mtime = std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_600_000_000);
+    fs::File::options()
+        .write(true)
+        .open(&file)
+        .expect("open file for set_times")
+        .set_times(fs::FileTimes::new().set_modified(mtime))
+        .expect("set mtime");
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&file, fs::Permissions::from_mode(0o755)).expect("set permissions");
+    }
+
+    let mut config = make_config(project.root());
+    config.preserve_metadata = true;
+    convert_gbk_file(&file, &config).expect("convert gbk file");
+
+    let metadata = fs::metadata(&file).expect("read metadata");
+    assert_eq!(
+        fs::read_to_string(&file).expect("read converted file"),
+        input
+    );
+    assert_eq!(metadata.modified().expect("read mtime"), mtime);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o755);
+    }
+}
+
+// 多线程处理时统计与错误应正确合并
+#[test]
+fn run_with_multiple_jobs_merges_stats_and_errors() {
+    let project = TestProject::new();
+    let mut converted = Vec::new();
+    for i in 0..12 {
+        let content = format!("并行转换文件编号{}", i);
+        converted.push((
+            project.write_gbk(&format!("src/file{:02}.c", i), &content),
+            content,
+        ));
+    }
+    project.write_utf8("src/already.c", "已经是 UTF-8");
+    let invalid = project.write_bytes("src/invalid.c", &truncated_gbk("并行处理中损坏的文件"));
+
+    let mut config = make_config(project.root());
+    config.jobs = 4;
+    config.min_confidence = 0.5;
+    config.show_info = true;
+
+    let result = run(&config).expect("run with multiple jobs");
+    assert_eq!(result.stats.converted, 12);
+    assert_eq!(result.stats.no_conversion, 1);
+    assert_eq!(result.stats.failed, 1);
+    assert!(result.errors.contains_key(&invalid));
+    for (path, content) in converted {
+        assert_eq!(
+            fs::read_to_string(&path).expect("read converted file"),
+            content
+        );
+    }
+}
+
+// --gitignore 应遵循各级 .gitignore / .ignore 规则并跳过 .git 目录
+#[test]
+fn run_with_gitignore_honors_nested_ignore_files() {
+    let project = TestProject::new();
+    let converted = project.write_gbk("src/main.c", "遵循 gitignore 的转换");
+    let untouched = [
+        project.write_gbk("target/build.c", "被根目录 .gitignore 忽略"),
+        project.write_gbk("src/gen/out.c", "被子目录 .gitignore 忽略"),
+        project.write_gbk("vendor/lib.c", "被 .ignore 忽略"),
+        project.write_gbk(".git/hooks/legacy.c", "位于 .git 目录"),
+    ];
+    let before: Vec<_> = untouched
+        .iter()
+        .map(|p| fs::read(p).expect("read untouched before"))
+        .collect();
+    project.write_utf8(".gitignore", "target/\n");
+    project.write_utf8("src/.gitignore", "gen/\n");
+    project.write_utf8(".ignore", "vendor/\n");
+
+    let mut config = make_config(project.root());
+    config.gitignore = true;
+    config.min_confidence = 0.5;
+
+    let result = run(&config).expect("run with gitignore");
+    assert!(result.errors.is_empty());
+    assert_eq!(result.stats.converted, 1);
+    assert_eq!(
+        fs::read_to_string(&converted).expect("read converted file"),
+        "遵循 gitignore 的转换"
+    );
+    for (path, bytes) in untouched.iter().zip(before) {
+        assert_eq!(fs::read(path).expect("read untouched file"), bytes);
+    }
+}
+
+// JSON 报告应包含每个文件的路径、编码、动作、备份路径和错误，以及汇总统计
+#[test]
+fn run_json_report_contains_file_records_and_summary() {
+    let project = TestProject::new();
+    let converted = project.write_gbk("a.c", "生成 JSON 报告的转换文件");
+    let invalid = project.write_bytes("b.c", &truncated_gbk("报告中转换失败的文件"));
+    project.write_utf8("c.c", "already utf-8");
+
+    let mut config = make_config(project.root());
+    config.backup = true;
+    config.show_info = true;
+    config.min_confidence = 0.5;
+    config.format = OutputFormat::Json;
+
+    let result = run(&config).expect("run with json format");
+    let actions: Vec<_> = result.reports.iter().map(|r| r.action).collect();
+    assert_eq!(
+        actions,
+        vec![
+            FileAction::Converted,
+            FileAction::Failed,
+            FileAction::Unchanged
+        ]
+    );
+
+    let json = render_summary(&result, &config).expect("json summary");
+    let value: serde_json::Value = serde_json::from_str(&json).expect("parse json report");
+    let files = value["files"].as_array().expect("files array");
+    assert_eq!(files[0]["path"], converted.to_string_lossy().as_ref());
+    assert_eq!(files[0]["encoding"], "gbk");
+    assert_eq!(files[0]["action"], "converted");
+    assert_eq!(files[0]["target"], "utf-8");
+    assert!(files[0]["backup"]
+        .as_str()
+        .is_some_and(|b| b.ends_with("a.c.bak")));
+    assert_eq!(files[1]["path"], invalid.to_string_lossy().as_ref());
+    assert_eq!(files[1]["action"], "failed");
+    assert!(files[1]["error"].is_string());
+    assert_eq!(files[2]["encoding"], "utf-8");
+    assert_eq!(files[2]["confidence"], 1.0);
+    assert_eq!(value["summary"]["converted"], 1);
+    assert_eq!(value["summary"]["failed"], 1);
+    assert_eq!(value["summary"]["no_conversion"], 1);
+}
+
+#[test]
+fn render_summary_ndjson_emits_tagged_summary_line() {
+    let project = TestProject::new();
+    let mut config = make_config(project.root());
+    config.format = OutputFormat::Ndjson;
+
+    let result = run(&config).expect("run with ndjson format");
+    let line = render_summary(&result, &config).expect("ndjson summary");
+    let value: serde_json::Value = serde_json::from_str(&line).expect("parse ndjson summary");
+
+    assert!(!line.contains('\n'));
+    assert_eq!(value["type"], "summary");
+    assert_eq!(value["converted"], 0);
+}
+
+// 检查模式不应写入文件，并统计非 UTF-8 文件数量
+#[test]
+fn run_check_mode_reports_non_utf8_files_without_writing() {
+    let project = TestProject::new();
+    let gbk = project.write_gbk("legacy.c", "检查模式不应转换这个文件");
+    let gbk_before = fs::read(&gbk).expect("read gbk before");
+    project.write_utf8("ok.c", "fine");
+
+    let mut config = make_config(project.root());
+    config.check = true;
+
+    let result = run(&config).expect("run in check mode");
+    assert_eq!(result.stats.check_failed, 1);
+    assert_eq!(result.stats.converted, 0);
+    assert_eq!(result.stats.no_conversion, 1);
+    assert_eq!(result.reports[0].action, FileAction::CheckFailed);
+    assert_eq!(result.reports[0].encoding.as_deref(), Some("gbk"));
+    assert_eq!(fs::read(&gbk).expect("read gbk after"), gbk_before);
+}
+
+// pre-commit 传入的文件路径应只处理这些文件，并应用扩展名与忽略规则
+#[test]
+fn run_with_explicit_paths_processes_only_given_files() {
+    let project = TestProject::new();
+    let staged = project.write_gbk("src/staged.c", "暂存的文件需要转换");
+    let not_staged = project.write_gbk("src/other.c", "未暂存的文件不应处理");
+    let wrong_ext = project.write_gbk("src/notes.md", "扩展名不匹配");
+    let ignored = project.write_gbk("vendor/lib.c", "命中忽略规则");
+    project.write_ignore("vendor/\n");
+    let before: Vec<_> = [&not_staged, &wrong_ext, &ignored]
+        .iter()
+        .map(|p| fs::read(p).expect("read untouched before"))
+        .collect();
+
+    let mut config = make_config(project.root());
+    config.min_confidence = 0.5;
+    config.paths = vec![staged.clone(), wrong_ext.clone(), ignored.clone()];
+
+    let result = run(&config).expect("run with explicit paths");
+    assert!(result.errors.is_empty());
+    assert_eq!(result.stats.converted, 1);
+    assert_eq!(result.reports.len(), 1);
+    assert_eq!(
+        fs::read_to_string(&staged).expect("read staged file"),
+        "暂存的文件需要转换"
+    );
+    for (path, bytes) in [&not_staged, &wrong_ext, &ignored].iter().zip(before) {
+        assert_eq!(fs::read(path).expect("read untouched file"), bytes);
+    }
+}
+
+// 路径参数可以混合文件、目录和 glob 模式，重复匹配的文件只处理一次
+#[test]
+fn run_with_files_directories_and_globs() {
+    let project = TestProject::new();
+    let single = project.write_gbk("single.txt", "单独指定的文件");
+    let in_dir = project.write_gbk("dir/nested/a.h", "目录参数中的文件");
+    let globbed = project.write_gbk("src/deep/x/b.c", "glob 匹配的文件");
+    let glob_miss = project.write_gbk("src/deep/x/b.h", "glob 未匹配的文件");
+    let glob_miss_before = fs::read(&glob_miss).expect("read glob miss before");
+
+    let mut config = make_config(project.root());
+    config.min_confidence = 0.5;
+    config.paths = vec![
+        single.clone(),
+        project.path("dir"),
+        project.path("src/**/*.c"),
+        globbed.clone(),
+    ];
+
+    let result = run(&config).expect("run with mixed path arguments");
+    assert!(result.errors.is_empty());
+    assert_eq!(result.stats.converted, 3);
+    assert_eq!(result.reports.len(), 3);
+    assert_eq!(
+        fs::read_to_string(&single).expect("read single"),
+        "单独指定的文件"
+    );
+    assert_eq!(
+        fs::read_to_string(&in_dir).expect("read in dir"),
+        "目录参数中的文件"
+    );
+    assert_eq!(
+        fs::read_to_string(&globbed).expect("read globbed"),
+        "glob 匹配的文件"
+    );
+    assert_eq!(
+        fs::read(&glob_miss).expect("read glob miss"),
+        glob_miss_before
+    );
+}
+
+// 标准输入流模式应识别编码并输出 UTF-8，UTF-8 输入原样输出
+#[test]
+fn convert_stream_decodes_gbk_input_to_utf8_output() {
+    let project = TestProject::new();
+    let config = make_config(project.root());
+    let input = "管道中的中文内容用于编码识别，包含足够多的汉字来提高检测准确度。";
+
+    let mut output = Vec::new();
+    let report = convert_stream(&mut gbk_bytes(input).as_slice(), &mut output, &config)
+        .expect("convert gbk stream");
+    assert_eq!(report.action, FileAction::Converted);
+    assert_eq!(String::from_utf8(output).expect("utf8 output"), input);
+
+    let mut output = Vec::new();
+    let report =
+        convert_stream(&mut input.as_bytes(), &mut output, &config).expect("convert utf8 stream");
+    assert_eq!(report.action, FileAction::Unchanged);
+    assert_eq!(output, input.as_bytes());
+}
+
+// 标准输入流模式下 --check 只检查不输出，非目标编码的输入报告为检查失败
+#[test]
+fn convert_stream_with_check_writes_nothing() {
+    let project = TestProject::new();
+    let mut config = make_config(project.root());
+    config.check = true;
+    let input = "管道中的中文内容用于编码识别，包含足够多的汉字来提高检测准确度。";
+
+    let mut output = Vec::new();
+    let report = convert_stream(&mut gbk_bytes(input).as_slice(), &mut output, &config)
+        .expect("check gbk stream");
+    assert_eq!(report.action, FileAction::CheckFailed);
+    assert_eq!(report.encoding.as_deref(), Some("gbk"));
+    assert!(output.is_empty());
+
+    let report =
+        convert_stream(&mut input.as_bytes(), &mut output, &config).expect("check utf8 stream");
+    assert_eq!(report.action, FileAction::Unchanged);
+    assert!(output.is_empty());
+}
+
+// 大文件按块检测和转换，多字节字符跨越块边界时结果仍与整体处理一致
+#[test]
+fn large_files_are_detected_and_converted_across_chunk_boundaries() {
+    let project = TestProject::new();
+    let line = "大文件流式转换测试，跨越分块边界的中文内容。x\n";
+    let text = format!("a{}", line.repeat(35_000));
+    assert!(text.len() > 2 * 1024 * 1024);
+
+    let gbk_file = project.write_bytes("big_gbk.txt", &gbk_bytes(&text));
+    let utf8_file = project.write_bytes("big_utf8.txt", text.as_bytes());
+    let mut late_invalid = text.clone().into_bytes();
+    late_invalid.push(0xFF);
+    let late_invalid_file = project.write_bytes("late_invalid.txt", &late_invalid);
+
+    let config = make_config(project.root());
+    let (encoding, _) = scan_gbk_file(&gbk_file, &config)
+        .expect("scan big gbk")
+        .expect("big gbk detected");
+    assert_eq!(encoding, "gbk");
+    let (encoding, _) = scan_gbk_file(&utf8_file, &config)
+        .expect("scan big utf8")
+        .expect("big utf8 detected");
+    assert_eq!(encoding, "utf-8");
+    let late = scan_gbk_file(&late_invalid_file, &config).expect("scan late invalid");
+    assert_ne!(late.map(|(encoding, _)| encoding).as_deref(), Some("utf-8"));
+
+    convert_gbk_file(&gbk_file, &config).expect("convert big gbk");
+    assert_eq!(fs::read_to_string(&gbk_file).expect("read converted"), text);
+}
+
+// 置信度应反映解码结果的合理程度：错误编码解出的乱码得分很低，内容越多置信度越高
+#[test]
+fn detect_encoding_confidence_reflects_text_plausibility() {
+    let project = TestProject::new();
+    let mut config = make_config(project.root());
+
+    let long = "中文内容用于编码识别，包含足够多的汉字来提高检测准确度。";
+    let (encoding, long_confidence) = detect_encoding(&gbk_bytes(long), &config);
+    assert_eq!(encoding, encoding_rs::GBK);
+    assert!(long_confidence > 0.9, "{}", long_confidence);
+    let (_, short_confidence) = detect_encoding(&gbk_bytes("测试"), &config);
+    assert!(short_confidence < long_confidence, "{}", short_confidence);
+
+    let traditional = "繁體中文內容用於編碼識別，包含足夠多的漢字來提高檢測準確度。";
+    let (big5, _, _) = encoding_rs::BIG5.encode(traditional);
+    let (encoding, confidence) = detect_encoding(&big5, &config);
+    if encoding == encoding_rs::GBK {
+        assert!(confidence < 0.5, "big5 text misread as gbk: {}", confidence);
+    }
+
+    config.tld = Some("tw".to_string());
+    config.from = vec!["gbk".to_string(), "big5".to_string()];
+    let (encoding, confidence) = detect_encoding(&big5, &config);
+    assert_eq!(encoding, encoding_rs::BIG5);
+    assert!(confidence > 0.9, "{}", confidence);
+}
+
+// --candidates 应按得分列出候选编码，附带解码错误数和示例行
+#[test]
+fn run_with_candidates_lists_ranked_encodings() {
+    let project = TestProject::new();
+    let input = "// 繁體中文內容用於編碼識別\nint main() { return 0; }\n";
+    let (big5, _, _) = encoding_rs::BIG5.encode(input);
+    project.write_bytes("legacy.c", &big5);
+
+    let mut config = make_config(project.root());
+    config.scan_only = true;
+    config.candidates = 6;
+
+    let result = run(&config).expect("run with candidates");
+    let candidates = &result.reports[0].candidates;
+    assert_eq!(candidates.len(), 6);
+    assert_eq!(candidates[0].encoding, "big5");
+    assert_eq!(candidates[0].errors, 0);
+    assert_eq!(candidates[0].sample, "// 繁體中文內容用於編碼識別");
+    assert!(candidates[0].score > candidates[1].score);
+    assert!(candidates
+        .iter()
+        .any(|c| c.encoding == "gbk" && c.sample != candidates[0].sample));
+}
+
+// 配置文件中的路径覆盖规则应强制指定源编码或跳过，多条规则命中时以最后一条为准
+#[test]
+fn run_applies_path_overrides_from_config_file() {
+    let project = TestProject::new();
+    let (big5, _, _) = encoding_rs::BIG5.encode("繁體");
+    let forced = project.write_bytes("legacy/big5/short.c", &big5);
+    let skipped = project.write_gbk("vendor/lib.c", "第三方代码不应转换");
+    let kept = project.write_gbk("vendor/keep.c", "最后一条规则优先");
+    let skipped_before = fs::read(&skipped).expect("read skipped before");
+    project.write_bytes(
+        ".gbk2utf8.toml",
+        br#"
+[[overrides]]
+path = "big5/"
+encoding = "big5"
+
+[[overrides]]
+path = "vendor/**"
+encoding = "skip"
+
+[[overrides]]
+path = "vendor/keep.c"
+encoding = "gbk"
+"#,
+    );
+
+    let mut config = make_config(project.root());
+    config
+        .load_project_config(&no_cli_args())
+        .expect("load project config");
+
+    let result = run(&config).expect("run with overrides");
+    assert!(result.errors.is_empty());
+    assert_eq!(result.stats.converted, 2);
+    assert_eq!(fs::read_to_string(&forced).expect("read forced"), "繁體");
+    assert_eq!(
+        fs::read_to_string(&kept).expect("read kept"),
+        "最后一条规则优先"
+    );
+    assert_eq!(fs::read(&skipped).expect("read skipped"), skipped_before);
+    let excluded = result
+        .reports
+        .iter()
+        .find(|r| r.path == skipped)
+        .expect("report for skipped file");
+    assert_eq!(excluded.action, FileAction::Excluded);
+}
+
+// 路径覆盖规则强制指定编码的文件再次运行时已是目标编码，报告为无需转换，不会被重复解码成乱码；
+// scan_gbk_file 同样应用覆盖规则并跳过二进制文件
+#[test]
+fn run_twice_keeps_overridden_files_converted_once() {
+    let project = TestProject::new();
+    let text = "// 中文注释，第二次运行不应再被解码\nint a;\n";
+    let file = project.write_gbk("legacy/a.c", text);
+    let skipped = project.write_gbk("vendor/b.c", "第三方代码");
+    let binary = project.write_bytes("data/blob.c", b"int a;\x00\x01\x02\xB2\xE2");
+    project.write_bytes(
+        ".gbk2utf8.toml",
+        br#"
+[[overrides]]
+path = "legacy/"
+encoding = "gbk"
+
+[[overrides]]
+path = "vendor/"
+encoding = "skip"
+"#,
+    );
+
+    let mut config = make_config(project.root());
+    config
+        .load_project_config(&no_cli_args())
+        .expect("load project config");
+
+    let result = run(&config).expect("first run");
+    assert_eq!(result.stats.converted, 1);
+    assert_eq!(fs::read_to_string(&file).expect("read converted"), text);
+
+    let result = run(&config).expect("second run");
+    assert!(result.errors.is_empty());
+    assert_eq!(result.stats.converted, 0);
+    let report = result
+        .reports
+        .iter()
+        .find(|r| r.path == file)
+        .expect("report for overridden file");
+    assert_eq!(report.action, FileAction::Unchanged);
+    assert_eq!(report.encoding.as_deref(), Some("utf-8"));
+    assert_eq!(fs::read_to_string(&file).expect("read again"), text);
+
+    assert_eq!(
+        scan_gbk_file(&file, &config).expect("scan overridden file"),
+        Some(("utf-8".to_string(), 1.0))
+    );
+    assert_eq!(
+        scan_gbk_file(&skipped, &config).expect("scan skipped file"),
+        None
+    );
+    assert_eq!(
+        scan_gbk_file(&binary, &config).expect("scan binary file"),
+        None
+    );
+}
+
+// 项目配置文件中的选项应作为默认值生效，命令行显式指定的参数优先
+#[test]
+fn load_project_config_merges_file_options_under_cli_flags() {
+    let project = TestProject::new();
+    project.write_bytes(
+        ".gbk2utf8.toml",
+        b"extensions = [\"txt\"]\nmin-confidence = 0.5\nbackup = true\nlang = \"en\"\n",
+    );
+    let dir = project.root().to_string_lossy().to_string();
+
+    let matches = Config::command().get_matches_from(["gbk2utf8", "-d", &dir, "-e", "c,h"]);
+    let mut config = Config::from_arg_matches(&matches).expect("parse cli args");
+    config
+        .load_project_config(&matches)
+        .expect("load project config");
+
+    assert_eq!(config.extensions, vec!["c".to_string(), "h".to_string()]);
+    assert_eq!(config.min_confidence, 0.5);
+    assert!(config.backup);
+    assert_eq!(config.lang, LangOption::En);
+    assert_eq!(config.project_config, Some(project.path(".gbk2utf8.toml")));
+
+    let printed = config.render_project_config();
+    assert!(printed.contains("min-confidence = 0.5"), "{}", printed);
+    assert!(
+        printed.contains("extensions = [\"c\", \"h\"]"),
+        "{}",
+        printed
+    );
+}
+
+// 没有 .gbk2utf8.toml 时应读取 Cargo.toml 的 [package.metadata.gbk2utf8]
+#[test]
+fn load_project_config_falls_back_to_cargo_metadata() {
+    let project = TestProject::new();
+    project.write_bytes(
+        "Cargo.toml",
+        b"[package]\nname = \"demo\"\n\n[package.metadata.gbk2utf8]\nto = \"gbk\"\njobs = 4\n\n[[package.metadata.gbk2utf8.overrides]]\npath = \"vendor/\"\nencoding = \"skip\"\n",
+    );
+
+    let mut config = make_config(project.root());
+    config
+        .load_project_config(&no_cli_args())
+        .expect("load cargo metadata");
+
+    assert_eq!(config.to, "gbk");
+    assert_eq!(config.jobs, 4);
+    assert_eq!(
+        config.overrides.lookup(&project.path("vendor/lib.c")),
+        Some(PathOverride::Skip)
+    );
+}
+
+// 带备份的转换会写入清单，restore 据此恢复原文件，clean-backups 删除剩余的备份
+#[test]
+fn restore_and_clean_backups_follow_manifest() {
+    let project = TestProject::new();
+    let restored = project.write_gbk("src/a.c", "需要恢复的原始内容");
+    let kept = project.write_gbk("b.txt", "转换后保留的内容");
+    let original = fs::read(&restored).expect("read original");
+
+    let mut config = make_config(project.root());
+    config.backup = true;
+    let result = run(&config).expect("run with backup");
+    assert_eq!(result.stats.converted, 2);
+
+    let manifest = BackupManifest::load(project.root()).expect("load manifest");
+    assert_eq!(manifest.entries.len(), 2);
+    assert_eq!(manifest.entries[1].path, Path::new("src/a.c"));
+    assert_eq!(manifest.entries[1].backup, Path::new("src/a.c.bak"));
+
+    // 只恢复一个文件：从清单中去掉另一条
+    BackupManifest {
+        entries: vec![manifest.entries[1].clone()],
+    }
+    .save(project.root())
+    .expect("write manifest");
+    let restore = restore_backups(&config).expect("restore backups");
+    assert_eq!(restore.processed.len(), 1);
+    assert!(restore.errors.is_empty());
+    assert_eq!(fs::read(&restored).expect("read restored"), original);
+    assert!(!project.root().join("src/a.c.bak").exists());
+    assert!(!manifest_path(project.root()).exists());
+
+    let err = clean_backups(&config).expect_err("no manifest left");
+    assert_eq!(err.kind(), io::ErrorKind::NotFound);
+
+    BackupManifest {
+        entries: vec![manifest.entries[0].clone()],
+    }
+    .save(project.root())
+    .expect("write manifest");
+    let clean = clean_backups(&config).expect("clean backups");
+    assert_eq!(clean.processed.len(), 1);
+    assert!(!project.root().join("b.txt.bak").exists());
+    assert_eq!(
+        fs::read_to_string(&kept).expect("read kept"),
+        "转换后保留的内容"
+    );
+    assert!(!manifest_path(project.root()).exists());
+}
+
+// --backup-dir 按相对路径集中存放备份，不覆盖已有备份，遍历时跳过备份目录，restore 恢复为最早的原文件
+#[test]
+fn run_with_backup_dir_mirrors_layout_without_overwriting() {
+    let project = TestProject::new();
+    let file = project.write_gbk("src/a.c", "第一次转换前的内容");
+    let first = fs::read(&file).expect("read first original");
+
+    let mut config = make_config(project.root());
+    config.backup_dir = Some(".gbk2utf8-backup".to_string());
+    let result = run(&config).expect("first run");
+    assert_eq!(result.stats.converted, 1);
+    let first_backup = result.reports[0].backup.clone().expect("first backup");
+    let backup_root = project.root().join(".gbk2utf8-backup");
+    assert!(first_backup.starts_with(&backup_root));
+    assert!(first_backup.ends_with("src/a.c"));
+    assert!(!project.root().join("src/a.c.bak").exists());
+
+    project.write_gbk("src/a.c", "第二次转换前的内容");
+    let result = run(&config).expect("second run");
+    // 备份目录中的 GBK 副本不应被当作源文件处理
+    assert_eq!(result.stats.converted, 1);
+    assert_eq!(result.reports.len(), 1);
+    let second_backup = result.reports[0].backup.clone().expect("second backup");
+    assert_ne!(second_backup, first_backup);
+    assert_eq!(fs::read(&first_backup).expect("read first backup"), first);
+
+    restore_backups(&config).expect("restore backups");
+    assert_eq!(fs::read(&file).expect("read restored"), first);
+}
+
+// --dir 写相对路径、--backup-dir 写绝对路径时，第二次运行仍能识别并跳过备份目录
+#[test]
+fn run_skips_absolute_backup_dir_under_relative_dir() {
+    let project = TestProject::new();
+    project.write_gbk("src/a.c", "// 第一次转换前的内容");
+    let cwd = std::env::current_dir().expect("current dir");
+    let up = "../".repeat(cwd.components().count() - 1);
+    let relative_dir =
+        Path::new(&up).join(project.root().strip_prefix("/").expect("absolute root"));
+    let backup_root = project.root().join("bk");
+
+    let mut config = make_config(project.root());
+    config.dir = relative_dir.to_string_lossy().into_owned();
+    config.backup_dir = Some(backup_root.to_string_lossy().into_owned());
+    let result = run(&config).expect("first run");
+    assert_eq!(result.stats.converted, 1);
+    let first_backup = result.reports[0].backup.clone().expect("first backup");
+
+    project.write_gbk("src/a.c", "// 第二次转换前的内容");
+    let result = run(&config).expect("second run");
+    assert_eq!(result.stats.converted, 1);
+    assert_eq!(result.reports.len(), 1);
+    assert_eq!(
+        fs::read(&first_backup).expect("read first backup"),
+        gbk_bytes("// 第一次转换前的内容")
+    );
+}
+
+// 识别编码时读完整个文件的，顺带统计换行符和 BOM，普通运行也能提示换行符混用
+#[test]
+fn run_reports_line_endings_from_detection_pass() {
+    let project = TestProject::new();
+    let gbk = project.write_bytes("legacy.c", &gbk_bytes("// 第一行注释\r\n// 第二行注释\n"));
+    let mut utf8_bom = b"\xEF\xBB\xBF".to_vec();
+    utf8_bom.extend_from_slice("// 已是 UTF-8\r\n".as_bytes());
+    let utf8 = project.write_bytes("bom.c", &utf8_bom);
+
+    let result = run(&make_config(project.root())).expect("run");
+    assert!(result.errors.is_empty());
+    let report = |path: &Path| {
+        result
+            .reports
+            .iter()
+            .find(|r| r.path == path)
+            .expect("file report")
+            .clone()
+    };
+    let legacy = report(&gbk);
+    assert_eq!(legacy.action, FileAction::Converted);
+    let line_endings = legacy.line_endings.expect("line endings of gbk file");
+    assert_eq!((line_endings.crlf, line_endings.lf), (1, 1));
+    assert!(!legacy.bom);
+
+    let bom = report(&utf8);
+    assert_eq!(bom.action, FileAction::Unchanged);
+    assert!(bom.bom);
+    assert_eq!(bom.line_endings.map(|l| (l.crlf, l.lf)), Some((1, 0)));
+}
+
+// --eol 在转换时改写换行符，已是 UTF-8 的文件也会只改写换行符；跨块的 \r\n 不会被拆开
+#[test]
+fn run_with_eol_normalizes_line_endings() {
+    let project = TestProject::new();
+    let gbk = project.write_bytes(
+        "legacy.c",
+        &gbk_bytes("第一行注释\r\n第二行注释\n第三行\r\n"),
+    );
+    let mut long_line = "a".repeat(64 * 1024 - 1);
+    long_line.push_str("\r\nb\r\n");
+    let utf8 = project.write_bytes("long.txt", long_line.as_bytes());
+    let lf = project.write_bytes("lf.h", b"int a;\nint b;\n");
+    let lf_before = fs::metadata(&lf)
+        .expect("stat lf")
+        .modified()
+        .expect("mtime");
+
+    let mut config = make_config(project.root());
+    config.min_confidence = 0.0;
+    config.scan_only = true;
+    let result = run(&config).expect("scan line endings");
+    let mixed = result
+        .reports
+        .iter()
+        .find(|r| r.path == gbk)
+        .and_then(|r| r.line_endings)
+        .expect("line endings of gbk file");
+    assert!(mixed.is_mixed());
+    assert_eq!((mixed.crlf, mixed.lf), (2, 1));
+
+    config.scan_only = false;
+    config.eol = EolOption::Lf;
+    let result = run(&config).expect("run with --eol lf");
+    assert!(result.errors.is_empty());
+    assert_eq!(result.stats.converted, 2);
+    assert_eq!(
+        fs::read_to_string(&gbk).expect("read gbk"),
+        "第一行注释\n第二行注释\n第三行\n"
+    );
+    assert_eq!(
+        fs::read_to_string(&utf8).expect("read utf8"),
+        long_line.replace("\r\n", "\n")
+    );
+    assert_eq!(
+        fs::metadata(&lf)
+            .expect("stat lf")
+            .modified()
+            .expect("mtime"),
+        lf_before
+    );
+}
+
+// 带 BOM 的 UTF-16 文件按 BOM 识别并转换；--bom 决定输出是否带 UTF-8 BOM，已是 UTF-8 的文件只改写 BOM
+#[test]
+fn run_with_bom_policy_converts_utf16_and_fixes_utf8_bom() {
+    let project = TestProject::new();
+    // encoding_rs 不提供 UTF-16 编码器，手工按小端序编码
+    let mut utf16_bytes = vec![0xFF, 0xFE];
+    utf16_bytes.extend("// 资源文件\r\n".encode_utf16().flat_map(u16::to_le_bytes));
+    let rc = project.write_bytes("app.h", &utf16_bytes);
+    let with_bom = project.write_bytes("bom.c", b"\xEF\xBB\xBFint a;\n");
+
+    let mut config = make_config(project.root());
+    let result = run(&config).expect("run with --bom keep");
+    assert!(result.errors.is_empty());
+    let report = result
+        .reports
+        .iter()
+        .find(|r| r.path == rc)
+        .expect("report for utf-16 file");
+    assert_eq!(report.encoding.as_deref(), Some("utf-16le"));
+    assert!(report.bom);
+    assert_eq!(
+        fs::read(&rc).expect("read converted"),
+        "\u{FEFF}// 资源文件\r\n".as_bytes()
+    );
+    assert_eq!(
+        fs::read(&with_bom).expect("read kept"),
+        b"\xEF\xBB\xBFint a;\n"
+    );
+
+    config.bom = BomOption::Strip;
+    let result = run(&config).expect("run with --bom strip");
+    assert_eq!(result.stats.converted, 2);
+    assert_eq!(
+        fs::read_to_string(&rc).expect("read stripped"),
+        "// 资源文件\r\n"
+    );
+    assert_eq!(fs::read(&with_bom).expect("read stripped"), b"int a;\n");
+}
+
+// 没有 BOM 的 UTF-16 和 UTF-32 按空字节分布识别，UTF-32 自行解码，均经 handle_file 转换为 UTF-8
+#[test]
+fn handle_file_detects_and_converts_utf16_and_utf32() {
+    let project = TestProject::new();
+    let text = "// 资源文件\r\n#define IDS_HELLO 101\r\n";
+    let utf16: Vec<u8> = text.encode_utf16().flat_map(u16::to_le_bytes).collect();
+    let utf32_be: Vec<u8> = text
+        .chars()
+        .flat_map(|c| (c as u32).to_be_bytes())
+        .collect();
+    let mut utf32_le = vec![0xFF, 0xFE, 0x00, 0x00];
+    utf32_le.extend(text.chars().flat_map(|c| (c as u32).to_le_bytes()));
+
+    let config = make_config(project.root());
+    for (name, bytes, encoding) in [
+        ("utf16.h", utf16, "utf-16le"),
+        ("utf32be.h", utf32_be, "utf-32be"),
+        ("utf32le.h", utf32_le, "utf-32le"),
+    ] {
+        let file = project.write_bytes(name, &bytes);
+        let scanned = scan_gbk_file(&file, &config).expect("scan utf-16/32 file");
+        assert_eq!(scanned, Some((encoding.to_string(), 1.0)));
+
+        let outcome = handle_file(&file, &config).expect("convert utf-16/32 file");
+        assert_eq!(outcome, FileProcessOutcome::Converted);
+        let converted = fs::read_to_string(&file).expect("read converted");
+        assert_eq!(converted.trim_start_matches('\u{FEFF}'), text);
+    }
+}
+
+// 含 NUL、已知文件头或大量控制字符的文件在检测编码前即按二进制跳过，内容保持不变；
+// UTF-16 文本不受影响，而看起来像 UTF-16 但解码后全是控制字符的数据文件仍按二进制跳过
+#[test]
+fn run_skips_binary_files_with_binary_outcome() {
+    let project = TestProject::new();
+    let with_nul = project.write_bytes("data.c", b"int a;\x00\x01\x02\xB2\xE2\xCA\xD4");
+    let png = project.write_bytes("logo.h", b"\x89PNG\r\n\x1A\n\xB2\xE2\xCA\xD4");
+    let controls = project.write_bytes("blob.txt", b"\x01\x02\x03\x04\xB2\xE2\x05\x06");
+    let gbk = project.write_gbk("main.c", "// 测试注释\n");
+    let utf16: Vec<u8> = "// 资源文件\r\n"
+        .encode_utf16()
+        .flat_map(u16::to_le_bytes)
+        .collect();
+    let rc = project.write_bytes("res.h", &utf16);
+    let values: Vec<u8> = (1..=40u16)
+        .cycle()
+        .take(400)
+        .flat_map(u16::to_le_bytes)
+        .collect();
+    let dat = project.write_bytes("table.dat", &values);
+
+    let mut config = make_config(project.root());
+    config.extensions.push("dat".to_string());
+    let result = run(&config).expect("run with binary files");
+    assert!(result.errors.is_empty());
+    for path in [&with_nul, &png, &controls, &dat] {
+        let report = result
+            .reports
+            .iter()
+            .find(|r| &r.path == path)
+            .expect("report for binary file");
+        assert_eq!(report.action, FileAction::Binary);
+        assert_eq!(report.encoding, None);
+    }
+    assert_eq!(
+        fs::read(&with_nul).expect("read binary"),
+        b"int a;\x00\x01\x02\xB2\xE2\xCA\xD4"
+    );
+    assert_eq!(
+        fs::read(&png).expect("read png"),
+        b"\x89PNG\r\n\x1A\n\xB2\xE2\xCA\xD4"
+    );
+    assert_eq!(fs::read(&dat).expect("read dat"), values);
+    assert_eq!(fs::read_to_string(&gbk).expect("read gbk"), "// 测试注释\n");
+    assert_eq!(
+        fs::read_to_string(&rc).expect("read utf-16"),
+        "// 资源文件\r\n"
+    );
+}
+
+// --mixed 逐行识别 UTF-8 行与 GBK 行混用的文件：report 报告各编码的行范围并跳过，fix 逐段解码后转换
+#[test]
+fn run_with_mixed_reports_segments_and_repairs_per_segment() {
+    let project = TestProject::new();
+    let mut content = "// 说明：这一行是 UTF-8\n".as_bytes().to_vec();
+    content.extend(b"int a;\n");
+    content.extend(gbk_bytes(
+        "// 这两行是后来用 GBK 编辑器添加的注释\n// 第二行中文注释\n",
+    ));
+    content.extend("// 又回到 UTF-8\n".as_bytes());
+    let file = project.write_bytes("mixed.c", &content);
+
+    let mut config = make_config(project.root());
+    config.mixed = MixedOption::Report;
+    let result = run(&config).expect("run with --mixed report");
+    assert!(result.errors.is_empty());
+    let report = &result.reports[0];
+    assert_eq!(report.action, FileAction::Mixed);
+    assert_eq!(report.encoding.as_deref(), Some("mixed"));
+    let segment = |encoding: &str, start_line, end_line| EncodingSegment {
+        encoding: encoding.to_string(),
+        start_line,
+        end_line,
+    };
+    assert_eq!(
+        report.segments,
+        vec![
+            segment("utf-8", 1, 2),
+            segment("gbk", 3, 4),
+            segment("utf-8", 5, 5)
+        ]
+    );
+    assert_eq!(fs::read(&file).expect("read unchanged"), content);
+
+    config.mixed = MixedOption::Fix;
+    let result = run(&config).expect("run with --mixed fix");
+    assert_eq!(result.stats.converted, 1);
+    assert_eq!(
+        fs::read_to_string(&file).expect("read repaired"),
+        "// 说明：这一行是 UTF-8\nint a;\n// 这两行是后来用 GBK 编辑器添加的注释\n// 第二行中文注释\n// 又回到 UTF-8\n"
+    );
+}
+
+// 纯 ASCII 文件已经是任何 ASCII 兼容的目标编码：--to gbk 时既不改写也不备份，检查模式也不报告
+#[test]
+fn run_to_gbk_leaves_ascii_files_unchanged() {
+    let project = TestProject::new();
+    let ascii = project.write_bytes("ascii.c", b"int main(void) { return 0; }\n");
+
+    let mut config = make_config(project.root());
+    config.to = "gbk".to_string();
+    config.backup = true;
+    let result = run(&config).expect("run with --to gbk");
+    assert!(result.errors.is_empty());
+    assert_eq!(result.reports[0].action, FileAction::Unchanged);
+    assert_eq!(result.reports[0].encoding.as_deref(), Some("gbk"));
+    assert_eq!(result.stats.converted, 0);
+    assert!(!project.root().join("ascii.c.bak").exists());
+    assert!(!manifest_path(project.root()).exists());
+
+    config.backup = false;
+    config.check = true;
+    let result = run(&config).expect("check with --to gbk");
+    assert_eq!(result.stats.check_failed, 0);
+    assert_eq!(
+        fs::read(&ascii).expect("read ascii"),
+        b"int main(void) { return 0; }\n"
+    );
+}
+
+// 转换符号链接时写入链接指向的文件并保留链接本身，有多个硬链接的文件转换后各链接内容一致
+#[cfg(unix)]
+#[test]
+fn run_converts_through_symlinks_and_hard_links() {
+    let project = TestProject::new();
+    let real = project.write_gbk("real/g.c", "// 链接指向的文件\n");
+    let link = project.root().join("proj/link.c");
+    fs::create_dir_all(link.parent().unwrap()).expect("create proj dir");
+    std::os::unix::fs::symlink("../real/g.c", &link).expect("create symlink");
+    let hard = project.write_gbk("proj/hard.c", "// 硬链接的文件\n");
+    let other = project.root().join("real/hard.c");
+    fs::hard_link(&hard, &other).expect("create hard link");
+
+    let config = make_config(&project.root().join("proj"));
+    let result = run(&config).expect("run through links");
+    assert!(result.errors.is_empty());
+    assert_eq!(result.stats.converted, 2);
+    assert!(fs::symlink_metadata(&link)
+        .expect("stat link")
+        .file_type()
+        .is_symlink());
+    assert_eq!(
+        fs::read_to_string(&real).expect("read link target"),
+        "// 链接指向的文件\n"
+    );
+    assert_eq!(
+        fs::read_to_string(&other).expect("read other hard link"),
+        "// 硬链接的文件\n"
+    );
+}
+
+// restore 同样写入符号链接指向的文件并保持硬链接，链接本身不会被替换成普通文件
+#[cfg(unix)]
+#[test]
+fn restore_writes_through_symlinks_and_hard_links() {
+    let project = TestProject::new();
+    let real = project.write_gbk("real/g.c", "// 链接指向的文件\n");
+    let link = project.root().join("proj/link.c");
+    fs::create_dir_all(link.parent().unwrap()).expect("create proj dir");
+    std::os::unix::fs::symlink("../real/g.c", &link).expect("create symlink");
+    let hard = project.write_gbk("proj/hard.c", "// 硬链接的文件\n");
+    let other = project.root().join("real/hard.c");
+    fs::hard_link(&hard, &other).expect("create hard link");
+
+    let mut config = make_config(&project.root().join("proj"));
+    config.backup = true;
+    let result = run(&config).expect("run through links");
+    assert_eq!(result.stats.converted, 2);
+
+    let restored = restore_backups(&config).expect("restore through links");
+    assert!(restored.errors.is_empty());
+    assert_eq!(restored.processed.len(), 2);
+    assert!(fs::symlink_metadata(&link)
+        .expect("stat link")
+        .file_type()
+        .is_symlink());
+    assert_eq!(
+        fs::read(&real).expect("read link target"),
+        gbk_bytes("// 链接指向的文件\n")
+    );
+    assert_eq!(
+        fs::read(&other).expect("read other hard link"),
+        gbk_bytes("// 硬链接的文件\n")
+    );
+    assert!(!project.root().join("proj/hard.c.bak").exists());
+}
+
+// 命令行传入的 --dir 之外的文件在清单中记录为绝对路径，restore 不会按 --dir 解析到错误的位置
+#[test]
+fn restore_files_outside_dir_recorded_with_absolute_paths() {
+    let project = TestProject::new();
+    fs::create_dir_all(project.root().join("sub")).expect("create sub dir");
+    let file = project.write_gbk("other/a.c", "// 目录之外的文件\n");
+    let cwd = std::env::current_dir().expect("current dir");
+    let up = "../".repeat(cwd.components().count() - 1);
+    let relative_file = Path::new(&up).join(file.strip_prefix("/").expect("absolute path"));
+
+    let mut config = make_config(&project.root().join("sub"));
+    config.backup = true;
+    config.paths = vec![relative_file];
+    let result = run(&config).expect("run with file outside dir");
+    assert_eq!(result.stats.converted, 1);
+    let manifest = BackupManifest::load(&project.root().join("sub")).expect("load manifest");
+    assert!(manifest.entries[0].path.is_absolute());
+    assert!(manifest.entries[0].backup.is_absolute());
+
+    let restored = restore_backups(&config).expect("restore file outside dir");
+    assert!(restored.errors.is_empty());
+    assert_eq!(
+        fs::read(&file).expect("read restored"),
+        gbk_bytes("// 目录之外的文件\n")
+    );
+}
+
+// 结构化报告不受 -i 影响：置信度不足时总是给出识别结果，动作为 uncertain 而不是 skipped
+#[test]
+fn inspect_file_reports_low_confidence_regardless_of_show_info() {
+    let project = TestProject::new();
+    let file = project.write_gbk("low.c", "// 初始化串口\nint a;\n");
+
+    let mut config = make_config(project.root());
+    config.min_confidence = 0.999;
+    let (report, outcome) = inspect_file(&file, &config);
+    assert_eq!(outcome.expect("inspect"), FileProcessOutcome::NoConversion);
+    assert_eq!(report.action, FileAction::Uncertain);
+    assert_eq!(report.encoding.as_deref(), Some("gbk"));
+    assert!(report.confidence.is_some());
+
+    config.show_info = true;
+    let (with_info, _) = inspect_file(&file, &config);
+    assert_eq!(with_info, report);
+}
+
+// 日文、韩文编码不按常用汉字打分：--from 中的 Shift_JIS、EUC-KR 文件在默认阈值下也能转换，
+// 只有一条短中文注释的 GBK 文件在默认设置下同样能转换
+#[test]
+fn handle_file_converts_non_chinese_sources_and_short_gbk_comments() {
+    let project = TestProject::new();
+    let japanese =
+        "// シリアルポートを初期化する\nvoid init(void);\n// 受信バッファをクリアします\n";
+    let korean = "// 직렬 포트를 초기화합니다\nvoid init(void);\n// 수신 버퍼를 지웁니다\n";
+    let (sjis, _, _) = encoding_rs::SHIFT_JIS.encode(japanese);
+    let (euc_kr, _, _) = encoding_rs::EUC_KR.encode(korean);
+    let sjis_file = project.write_bytes("sjis.c", &sjis);
+    let euc_kr_file = project.write_bytes("euckr.c", &euc_kr);
+    let short = project.write_gbk("short.c", "// 初始化\nint main(void) { return 0; }\n");
+
+    let mut config = make_config(project.root());
+    let outcome = handle_file(&short, &config).expect("convert short gbk file");
+    assert_eq!(outcome, FileProcessOutcome::Converted);
+    assert_eq!(
+        fs::read_to_string(&short).expect("read short"),
+        "// 初始化\nint main(void) { return 0; }\n"
+    );
+
+    for (file, from, tld, text) in [
+        (&sjis_file, "shift_jis", "jp", japanese),
+        (&euc_kr_file, "euc-kr", "kr", korean),
+    ] {
+        config.from = vec![from.to_string()];
+        config.tld = Some(tld.to_string());
+        let (report, outcome) = inspect_file(file, &config);
+        assert_eq!(
+            outcome.expect("convert file"),
+            FileProcessOutcome::Converted
+        );
+        assert_eq!(report.encoding.as_deref(), Some(from));
+        assert!(report.confidence.unwrap() >= config.min_confidence);
+        assert_eq!(fs::read_to_string(file).expect("read converted"), text);
+    }
+}
+
+// --candidates 对非中文编码按严格解码打分，得分相同时 chardetng 的猜测在前，
+// 不在固定列表中的猜测（如 windows-1252）也会列出
+#[test]
+fn rank_candidates_lists_chardetng_guess_for_non_chinese_files() {
+    let project = TestProject::new();
+    let (euc_kr, _, _) =
+        encoding_rs::EUC_KR.encode("// 직렬 포트를 초기화합니다\n// 수신 버퍼를 지웁니다\n");
+    let (latin, _, _) =
+        encoding_rs::WINDOWS_1252.encode("Café crème brûlée, naïve façade.\nÜber die Straße.\n");
+    project.write_bytes("korean.txt", &euc_kr);
+    project.write_bytes("latin.txt", &latin);
+
+    let mut config = make_config(project.root());
+    config.scan_only = true;
+    config.candidates = 3;
+    config.tld = Some("kr".to_string());
+    let result = run(&config).expect("run with candidates");
+    for (report, encoding) in result.reports.iter().zip(["euc-kr", "windows-1252"]) {
+        assert_eq!(report.candidates[0].encoding, encoding);
+        assert_eq!(report.candidates[0].errors, 0);
+    }
+}
+
+// 非中文候选编码出现非法序列即得 0 分；单字节编码按字母占比和大小写打分，
+// GBK 文本按 windows-1252 解出的乱码得分很低，真正的 windows-1252 文本置信度不受影响
+#[test]
+fn rank_candidates_scores_wrong_non_chinese_candidates_low() {
+    let project = TestProject::new();
+    let (sjis, _, _) = encoding_rs::SHIFT_JIS
+        .encode("// シリアルポートを初期化する\nvoid init(void);\n// 受信バッファをクリアします\n");
+    let sjis_file = project.write_bytes("sjis.c", &sjis);
+    let gbk_file = project.write_gbk(
+        "gbk.c",
+        "// 初始化串口，清空接收缓冲区\nint main(void) { return 0; }\n",
+    );
+    let (latin, _, _) =
+        encoding_rs::WINDOWS_1252.encode("Café crème brûlée, naïve façade.\nÜber die Straße.\n");
+    let latin_file = project.write_bytes("latin.txt", &latin);
+
+    let mut config = make_config(project.root());
+    config.scan_only = true;
+    config.candidates = 8;
+    config.from = vec!["gbk".to_string(), "windows-1252".to_string()];
+    let result = run(&config).expect("run with candidates");
+    let report = |path: &Path| {
+        result
+            .reports
+            .iter()
+            .find(|r| r.path == path)
+            .expect("file report")
+    };
+
+    let candidates = &report(&sjis_file).candidates;
+    assert_eq!(candidates[0].encoding, "shift_jis");
+    assert!(candidates
+        .iter()
+        .filter(|c| c.errors > 0)
+        .all(|c| c.score == 0.0));
+    assert!(candidates
+        .iter()
+        .any(|c| c.encoding == "euc-kr" && c.errors > 0));
+
+    let candidates = &report(&gbk_file).candidates;
+    assert_eq!(candidates[0].encoding, "gbk");
+    let latin_score = candidates
+        .iter()
+        .find(|c| c.encoding == "windows-1252")
+        .expect("windows-1252 candidate")
+        .score;
+    assert!(latin_score < 0.3, "{}", latin_score);
+
+    let latin = report(&latin_file);
+    assert_eq!(latin.encoding.as_deref(), Some("windows-1252"));
+    assert_eq!(latin.action, FileAction::ScanOnly);
+}
+
+// 备份清单每行一条记录，转换过程中逐条追加；运行被中断时写了一半的最后一行会被忽略
+#[test]
+fn backup_manifest_is_appended_per_entry_and_tolerates_truncated_line() {
+    let project = TestProject::new();
+    let first = project.write_gbk("a.c", "// 第一个文件\n");
+    let second = project.write_gbk("b.c", "// 第二个文件\n");
+
+    let mut config = make_config(project.root());
+    config.backup = true;
+    config.jobs = 2;
+    let result = run(&config).expect("run with backup");
+    assert!(result.errors.is_empty());
+    let manifest = manifest_path(project.root());
+    let content = fs::read_to_string(&manifest).expect("read manifest");
+    assert_eq!(content.lines().count(), 2);
+    assert!(content
+        .lines()
+        .all(|line| serde_json::from_str::<BackupEntry>(line).is_ok()));
+
+    let mut file = fs::OpenOptions::new()
+        .append(true)
+        .open(&manifest)
+        .expect("open manifest");
+    io::Write::write_all(&mut file, b"{\"path\":\"c.c\",\"bac").expect("append partial line");
+    let restore = restore_backups(&config).expect("restore with truncated manifest");
+    assert_eq!(restore.processed.len(), 2);
+    assert_eq!(
+        fs::read(&first).expect("read first"),
+        gbk_bytes("// 第一个文件\n")
+    );
+    assert_eq!(
+        fs::read(&second).expect("read second"),
+        gbk_bytes("// 第二个文件\n")
+    );
+}